@@ -748,6 +748,26 @@ impl Buffer {
         WordCursor::new(&self.text, offset).next_code_boundary()
     }
 
+    /// The start of the word `offset` is in, with `word_chars` taken as part
+    /// of words, see `LapceLanguage::word_chars`
+    pub fn prev_code_boundary_with(
+        &self,
+        offset: usize,
+        word_chars: &[char],
+    ) -> usize {
+        WordCursor::new(&self.text, offset).prev_code_boundary_with(word_chars)
+    }
+
+    /// The end of the word `offset` is in, with `word_chars` taken as part
+    /// of words
+    pub fn next_code_boundary_with(
+        &self,
+        offset: usize,
+        word_chars: &[char],
+    ) -> usize {
+        WordCursor::new(&self.text, offset).next_code_boundary_with(word_chars)
+    }
+
     pub fn move_left(&self, offset: usize, mode: Mode, count: usize) -> usize {
         let line = self.line_of_offset(offset);
         let line_start_offset = self.offset_of_line(line);
@@ -1113,4 +1133,17 @@ mod test {
         buffer.do_undo();
         assert!(buffer.is_pristine());
     }
+
+    #[test]
+    fn test_code_boundary_with() {
+        let buffer = Buffer::new("a { background-c: 1 }");
+        assert_eq!(buffer.prev_code_boundary(16), 15);
+        assert_eq!(buffer.prev_code_boundary_with(16, &['-']), 4);
+        assert_eq!(buffer.next_code_boundary_with(6, &['-']), 16);
+
+        let buffer = Buffer::new("list.empty? && x");
+        assert_eq!(buffer.next_code_boundary(7), 10);
+        assert_eq!(buffer.next_code_boundary_with(7, &['?', '!']), 11);
+        assert_eq!(buffer.prev_code_boundary_with(11, &['?', '!']), 5);
+    }
 }
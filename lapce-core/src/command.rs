@@ -213,8 +213,44 @@ pub enum FocusCommand {
     ListNext,
     #[strum(serialize = "list.previous")]
     ListPrevious,
+    #[strum(serialize = "list.first")]
+    ListFirst,
+    #[strum(serialize = "list.last")]
+    ListLast,
     #[strum(serialize = "list.expand")]
     ListExpand,
+    #[strum(serialize = "completion.next")]
+    CompletionNext,
+    #[strum(serialize = "completion.previous")]
+    CompletionPrevious,
+    #[strum(serialize = "completion.page_next")]
+    CompletionPageNext,
+    #[strum(serialize = "completion.page_previous")]
+    CompletionPagePrevious,
+    #[strum(serialize = "completion.first")]
+    CompletionFirst,
+    #[strum(serialize = "completion.last")]
+    CompletionLast,
+    #[strum(serialize = "get_completion")]
+    GetCompletion,
+    #[strum(serialize = "completion.accept")]
+    CompletionAccept,
+    #[strum(serialize = "completion.cancel")]
+    CompletionCancel,
+    #[strum(serialize = "completion.toggle_documentation")]
+    CompletionToggleDocumentation,
+    #[strum(serialize = "completion.scroll_documentation_up")]
+    CompletionScrollDocumentationUp,
+    #[strum(serialize = "completion.scroll_documentation_down")]
+    CompletionScrollDocumentationDown,
+    #[strum(serialize = "completion.debug_dump")]
+    CompletionDebugDump,
+    #[strum(message = "Toggle Snippet Completions")]
+    #[strum(serialize = "completion.toggle_snippets")]
+    CompletionToggleSnippets,
+    #[strum(message = "Toggle Keyword Completions")]
+    #[strum(serialize = "completion.toggle_keywords")]
+    CompletionToggleKeywords,
     #[strum(serialize = "jump_to_next_snippet_placeholder")]
     JumpToNextSnippetPlaceholder,
     #[strum(serialize = "jump_to_prev_snippet_placeholder")]
@@ -83,6 +83,10 @@ struct SyntaxProperties {
     /// `walk_tree`. If unsure, use `DEFAULT_CODE_LENS_LIST` and
     /// `DEFAULT_CODE_LENS_IGNORE_LIST`.
     code_lens: (&'static [&'static str], &'static [&'static str]),
+    /// The characters other than letters, digits and `_` that identifiers
+    /// can have, which completion takes as part of the word being typed.
+    /// `['-']` for html, `['?', '!']` for ruby, for example.
+    word_chars: &'static [char],
     /// File name extensions to determine the language.  `["py"]` for python,
     /// `["rs"]` for rust, for example.
     extensions: &'static [&'static str],
@@ -146,6 +150,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
             &["source_file", "impl_item", "trait_item", "declaration_list"],
             &["source_file", "use_declaration", "line_comment"],
         ),
+        word_chars: &[],
         extensions: &["rs"],
     },
     #[cfg(feature = "lang-go")]
@@ -165,6 +170,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
             ],
             &["source_file", "comment", "line_comment"],
         ),
+        word_chars: &[],
         extensions: &["go"],
     },
     #[cfg(feature = "lang-javascript")]
@@ -175,6 +181,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "  ",
         code_lens: (&["source_file", "program"], &["source_file"]),
+        word_chars: &[],
         extensions: &["js"],
     },
     #[cfg(feature = "lang-javascript")]
@@ -185,6 +192,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "  ",
         code_lens: (&["source_file", "program"], &["source_file"]),
+        word_chars: &[],
         extensions: &["jsx"],
     },
     #[cfg(feature = "lang-typescript")]
@@ -195,6 +203,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "    ",
         code_lens: (&["source_file", "program"], &["source_file"]),
+        word_chars: &[],
         extensions: &["ts"],
     },
     #[cfg(feature = "lang-typescript")]
@@ -205,6 +214,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "    ",
         code_lens: (&["source_file", "program"], &["source_file"]),
+        word_chars: &[],
         extensions: &["tsx"],
     },
     #[cfg(feature = "lang-python")]
@@ -226,6 +236,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
             ],
             &["source_file", "import_statement", "import_from_statement"],
         ),
+        word_chars: &[],
         extensions: &["py"],
     },
     #[cfg(feature = "lang-toml")]
@@ -236,6 +247,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "#",
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &[],
         extensions: &["toml"],
     },
     #[cfg(feature = "lang-php")]
@@ -246,6 +258,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &['$'],
         extensions: &["php"],
     },
     #[cfg(feature = "lang-elixir")]
@@ -256,6 +269,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "#",
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &['?', '!'],
         extensions: &["ex"],
     },
     #[cfg(feature = "lang-c")]
@@ -266,6 +280,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &[],
         extensions: &["c"],
     },
     #[cfg(feature = "lang-cpp")]
@@ -276,6 +291,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &[],
         extensions: &["cpp", "cxx", "cc", "c++", "hpp", "hxx", "hh", "h++"],
     },
     #[cfg(feature = "lang-json")]
@@ -286,6 +302,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "",
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &[],
         extensions: &["json"],
     },
     #[cfg(feature = "lang-md")]
@@ -296,6 +313,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "",
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &[],
         extensions: &["md"],
     },
     #[cfg(feature = "lang-ruby")]
@@ -306,6 +324,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "#",
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &['?', '!'],
         extensions: &["rb"],
     },
     #[cfg(feature = "lang-html")]
@@ -316,6 +335,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "",
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &['-'],
         extensions: &["html", "htm"],
     },
     #[cfg(feature = "lang-java")]
@@ -326,6 +346,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         comment: "//",
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
+        word_chars: &[],
         extensions: &["java"],
     },
 ];
@@ -366,6 +387,12 @@ impl LapceLanguage {
         self.properties().indent
     }
 
+    /// The characters other than letters, digits and `_` that are part of
+    /// identifiers, see `Buffer::prev_code_boundary_with`
+    pub fn word_chars(&self) -> &'static [char] {
+        self.properties().word_chars
+    }
+
     pub(crate) fn new_parser(&self) -> Parser {
         let language = (self.properties().language)();
         let mut parser = Parser::new();
@@ -546,4 +573,20 @@ mod test {
     fn test_java_lang() {
         assert_language(LapceLanguage::Java, &["java"]);
     }
+
+    #[test]
+    #[cfg(all(feature = "lang-html", feature = "lang-ruby"))]
+    fn test_word_chars() {
+        use crate::buffer::Buffer;
+
+        let buffer = Buffer::new("<div data-i");
+        let html = LapceLanguage::Html.word_chars();
+        assert_eq!(buffer.prev_code_boundary_with(11, html), 5);
+        let ruby = LapceLanguage::Ruby.word_chars();
+        assert_eq!(buffer.prev_code_boundary_with(11, ruby), 10);
+
+        let buffer = Buffer::new("list.empty?");
+        assert_eq!(buffer.prev_code_boundary_with(11, ruby), 5);
+        assert_eq!(buffer.prev_code_boundary_with(11, html), 11);
+    }
 }
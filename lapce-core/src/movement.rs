@@ -134,4 +134,21 @@ mod test {
         assert_eq!(0, Movement::Up.update_index(0, 5, 2, false));
         assert_eq!(2, Movement::Down.update_index(0, 5, 2, false));
     }
+
+    #[test]
+    fn test_boundaries() {
+        // Wrapping goes from one end to the other
+        assert_eq!(4, Movement::Up.update_index(0, 5, 1, true));
+        assert_eq!(0, Movement::Down.update_index(4, 5, 1, true));
+
+        // Otherwise it stays at the end
+        assert_eq!(0, Movement::Up.update_index(0, 5, 1, false));
+        assert_eq!(4, Movement::Down.update_index(4, 5, 1, false));
+
+        // An empty list has nothing to move to
+        for wrapping in [true, false] {
+            assert_eq!(0, Movement::Up.update_index(0, 0, 1, wrapping));
+            assert_eq!(0, Movement::Down.update_index(0, 0, 1, wrapping));
+        }
+    }
 }
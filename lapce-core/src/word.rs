@@ -114,10 +114,16 @@ impl<'a> WordCursor<'a> {
     }
 
     pub fn prev_code_boundary(&mut self) -> usize {
+        self.prev_code_boundary_with(&[])
+    }
+
+    /// Like `prev_code_boundary`, with `word_chars` taken as part of words
+    /// too, like the `-` of identifiers in some languages
+    pub fn prev_code_boundary_with(&mut self, word_chars: &[char]) -> usize {
         let mut candidate = self.inner.pos();
         while let Some(prev) = self.inner.prev_codepoint() {
             let prop_prev = get_word_property(prev);
-            if prop_prev != WordProperty::Other {
+            if prop_prev != WordProperty::Other && !word_chars.contains(&prev) {
                 break;
             }
             candidate = self.inner.pos();
@@ -126,10 +132,16 @@ impl<'a> WordCursor<'a> {
     }
 
     pub fn next_code_boundary(&mut self) -> usize {
+        self.next_code_boundary_with(&[])
+    }
+
+    /// Like `next_code_boundary`, with `word_chars` taken as part of words
+    /// too
+    pub fn next_code_boundary_with(&mut self, word_chars: &[char]) -> usize {
         let mut candidate = self.inner.pos();
         while let Some(prev) = self.inner.next_codepoint() {
             let prop_prev = get_word_property(prev);
-            if prop_prev != WordProperty::Other {
+            if prop_prev != WordProperty::Other && !word_chars.contains(&prev) {
                 break;
             }
             candidate = self.inner.pos();
@@ -13,8 +13,8 @@ use lapce_rpc::{
     source_control::DiffInfo, style::Style, terminal::TermId,
 };
 use lsp_types::{
-    CodeActionResponse, CompletionItem, CompletionResponse, Location, Position,
-    ProgressParams, PublishDiagnosticsParams, TextEdit,
+    CodeActionResponse, CompletionItem, CompletionItemKind, CompletionResponse,
+    Location, Position, ProgressParams, PublishDiagnosticsParams, TextEdit,
 };
 use serde_json::Value;
 use strum::{self, EnumMessage, IntoEnumIterator};
@@ -26,6 +26,7 @@ use crate::data::LapceWorkspace;
 use crate::document::BufferContent;
 use crate::rich_text::RichText;
 use crate::{
+    completion::ScoredCompletionItem,
     data::{EditorTabChild, SplitContent},
     editor::EditorLocationNew,
     keypress::{KeyMap, KeyPress},
@@ -390,8 +391,20 @@ pub enum LapceUICommand {
     OpenFile(PathBuf),
     OpenFileDiff(PathBuf, String),
     CancelCompletion(usize),
-    ResolveCompletion(BufferId, u64, usize, Box<CompletionItem>),
-    UpdateCompletion(usize, String, CompletionResponse),
+    /// The resolved item to accept, for the buffer at the revision and
+    /// offset it was accepted at, and the text typed after it, if any
+    ResolveCompletion(BufferId, u64, usize, Box<CompletionItem>, Option<String>),
+    UpdateCompletion(usize, String, CompletionResponse, Option<String>),
+    UpdateCompletionItem(usize, usize, Box<CompletionItem>),
+    UpdateFilteredCompletionItems(usize, String, Vec<ScoredCompletionItem>),
+    ToggleCompletionKind(CompletionItemKind),
+    ScrollCompletionDocumentation(f64),
+    /// The completion popup showed up, taking the rect, in window
+    /// coordinates, that other popups should keep clear of, like the hover
+    CompletionOpened(Rect),
+    /// The completion popup went away
+    CompletionClosed,
+    UpdateCompletionTriggerCharacters(BufferId, Vec<String>),
     UpdateHover(usize, Arc<Vec<RichText>>),
     UpdateCodeActions(PathBuf, u64, usize, CodeActionResponse),
     CancelPalette,
@@ -1,20 +1,62 @@
-use std::{fmt::Display, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Error;
-use druid::{ExtEventSink, Size, Target, WidgetId};
+use druid::{Color, ExtEventSink, Size, Target, WidgetId};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use itertools::Itertools;
-use lapce_core::movement::Movement;
-use lapce_rpc::buffer::BufferId;
-use lsp_types::{CompletionItem, CompletionResponse, Position};
+use lapce_core::{buffer::Buffer, language::LapceLanguage, movement::Movement};
+use lapce_rpc::{
+    buffer::BufferId,
+    proxy::{GetCompletionResponse, ReadDirResponse},
+};
+use lsp_types::{
+    CompletionContext, CompletionItem, CompletionItemKind, CompletionItemTag,
+    CompletionResponse, CompletionTextEdit, CompletionTriggerKind, Documentation,
+    InsertTextFormat, InsertTextMode, MarkupKind, Position, TextEdit,
+};
 use regex::Regex;
 use std::str::FromStr;
 
 use crate::{
     command::{LapceUICommand, LAPCE_UI_COMMAND},
+    config::Config,
+    hover::parse_hover_markdown,
     proxy::LapceProxy,
+    rich_text::{RichText, RichTextBuilder},
 };
 
+/// The narrowest the popup gets, so that short lists aren't cramped
+const MIN_POPUP_WIDTH: f64 = 200.0;
+/// The narrowest the popup gets when it's compact, see `icon_width`
+const MIN_COMPACT_POPUP_WIDTH: f64 = 100.0;
+/// The widest label, in characters, that lets the popup be compact
+const COMPACT_LABEL_CHARS: usize = 12;
+
+/// How many accepted labels are remembered per language
+const RECENT_CAPACITY: usize = 32;
+/// The score bonus for a recently accepted label. It's kept below what a
+/// single extra matched character is worth, so that it only breaks ties.
+const RECENT_BONUS: i64 = 8;
+
+/// How many labels the inline display mode shows at once
+const INLINE_ITEMS: usize = 8;
+/// What the labels are separated with in the inline display mode
+pub const INLINE_SEPARATOR: &str = " │ ";
+
+/// How many round-trip latencies are kept per language
+const LATENCY_CAPACITY: usize = 16;
+
+/// How far before and after the cursor, in bytes, `buffer_words` looks
+const BUFFER_WORDS_WINDOW: usize = 64 * 1024;
+
 #[derive(Debug)]
 pub struct Snippet {
     elements: Vec<SnippetElement>,
@@ -134,8 +176,17 @@ impl Snippet {
         self.elements.iter().map(|e| e.text()).join("")
     }
 
+    /// The tab stops of the snippet inserted at `pos`, in the order they're
+    /// visited: by their number, with the final `$0` last. Without a `$0`,
+    /// the snippet ends at the end of its text.
     pub fn tabs(&self, pos: usize) -> Vec<(usize, (usize, usize))> {
-        Self::elements_tabs(&self.elements, pos)
+        let mut tabs = Self::elements_tabs(&self.elements, pos);
+        if !tabs.iter().any(|(tab, _)| *tab == 0) {
+            let end = pos + self.text().len();
+            tabs.push((0, (end, end)));
+        }
+        tabs.sort_by_key(|(tab, _)| if *tab == 0 { usize::MAX } else { *tab });
+        tabs
     }
 
     pub fn elements_tabs(
@@ -227,7 +278,168 @@ impl Display for SnippetElement {
     }
 }
 
-#[derive(Clone, PartialEq)]
+/// How the case of the input is taken into account when matching
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaseMatching {
+    /// Always match case-insensitively
+    Ignore,
+    /// Match case-insensitively, unless the input has an uppercase letter
+    Smart,
+    /// Always match case-sensitively
+    Respect,
+}
+
+impl CaseMatching {
+    /// The case matching named `name` in the `completion-case-matching`
+    /// setting, falling back to `Ignore` for an unknown name
+    pub fn from_setting(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "smart" => CaseMatching::Smart,
+            "respect" => CaseMatching::Respect,
+            _ => CaseMatching::Ignore,
+        }
+    }
+
+    fn matcher(&self) -> SkimMatcherV2 {
+        let matcher = SkimMatcherV2::default();
+        match self {
+            CaseMatching::Ignore => matcher.ignore_case(),
+            CaseMatching::Smart => matcher.smart_case(),
+            CaseMatching::Respect => matcher.respect_case(),
+        }
+    }
+
+    /// Whether `input` is matched regardless of case
+    fn ignores_case(&self, input: &str) -> bool {
+        match self {
+            CaseMatching::Ignore => true,
+            CaseMatching::Smart => !input.chars().any(char::is_uppercase),
+            CaseMatching::Respect => false,
+        }
+    }
+}
+
+/// How the input is matched against the items
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MatchStrategy {
+    /// The characters of the input in order, with anything between them
+    Fuzzy,
+    /// The input as a whole, anywhere in the item
+    Substring,
+    /// The input as a whole, at the start of the item
+    Prefix,
+}
+
+impl MatchStrategy {
+    /// The strategy named `name` in the `completion-match-strategy`
+    /// setting, falling back to `Fuzzy` for an unknown name
+    pub fn from_setting(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "substring" => MatchStrategy::Substring,
+            "prefix" => MatchStrategy::Prefix,
+            _ => MatchStrategy::Fuzzy,
+        }
+    }
+
+    /// The score and the matched character indices of `input` in `text`,
+    /// or `None` if it doesn't match. Only the fuzzy strategy uses
+    /// `matcher`, the others take the case into account by themselves.
+    fn match_indices(
+        &self,
+        matcher: &SkimMatcherV2,
+        case_matching: CaseMatching,
+        text: &str,
+        input: &str,
+    ) -> Option<(i64, Vec<usize>)> {
+        let prefix = match self {
+            MatchStrategy::Fuzzy => return matcher.fuzzy_indices(text, input),
+            MatchStrategy::Substring => false,
+            MatchStrategy::Prefix => true,
+        };
+        let ignore_case = case_matching.ignores_case(input);
+        let text: Vec<char> = text.chars().collect();
+        let input: Vec<char> = input.chars().collect();
+        if input.len() > text.len() {
+            return None;
+        }
+        let eq = |a: char, b: char| {
+            a == b || (ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+        };
+        let last = if prefix { 0 } else { text.len() - input.len() };
+        let start = (0..=last).find(|start| {
+            input.iter().zip(&text[*start..]).all(|(a, b)| eq(*a, *b))
+        })?;
+        // About what the fuzzy matcher gives a character, less for a match
+        // further in
+        let len = input.len() as i64;
+        let score = (len * 16 - start as i64).max(len);
+        Some((score, (start..start + input.len()).collect()))
+    }
+}
+
+/// What a row of the popup shows for an item, independent of how it's
+/// painted
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionRow<'a> {
+    pub label: Cow<'a, str>,
+    /// Painted right after the label, see `ScoredCompletionItem::label_detail`
+    pub label_detail: Option<Cow<'a, str>>,
+    /// Painted on the right, see `ScoredCompletionItem::detail`
+    pub detail: Option<Cow<'a, str>>,
+    /// The server the item came from, if it's shown
+    pub source: Option<&'a str>,
+    pub kind: Option<CompletionItemKind>,
+    /// Painted as a swatch in place of the kind icon, see
+    /// `ScoredCompletionItem::color`
+    pub color: Option<Color>,
+    pub deprecated: bool,
+    /// The char indices of the label that matched the input
+    pub indices: &'a [usize],
+    pub selected: bool,
+}
+
+/// How the completion items are shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompletionDisplayMode {
+    /// A popup below or above the cursor, with a row per item
+    Popup,
+    /// A single row showing a page of `INLINE_ITEMS` labels
+    Inline,
+}
+
+impl CompletionDisplayMode {
+    /// The display mode named `name` in the `completion-display-mode`
+    /// setting, falling back to `Popup` for an unknown name
+    pub fn from_setting(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "inline" => CompletionDisplayMode::Inline,
+            _ => CompletionDisplayMode::Popup,
+        }
+    }
+}
+
+/// What the `on_accept` callback of `CompletionData` is told about an
+/// accepted item
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionAcceptance {
+    pub label: String,
+    pub kind: Option<CompletionItemKind>,
+    /// The server the item came from, if it's known
+    pub source: Option<String>,
+    /// The 0-based position of the item in the list as it was shown,
+    /// so the top item has rank 0
+    pub rank: usize,
+    /// The input typed since the session started
+    pub input: String,
+}
+
+pub type CompletionAcceptCallback = Arc<dyn Fn(&CompletionAcceptance) + Send + Sync>;
+
+/// Told the `accessible_label` of the selected item whenever the selection
+/// changes, for screen readers and other tools to announce it
+pub type CompletionAnnounceCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum CompletionStatus {
     Inactive,
     Started,
@@ -237,17 +449,198 @@ pub enum CompletionStatus {
 pub struct CompletionData {
     pub id: WidgetId,
     pub scroll_id: WidgetId,
+    pub documentation_id: WidgetId,
+    pub documentation_scroll_id: WidgetId,
+    /// The id of the session. What's still to come back for it, like
+    /// resolved items and background filtering, is keyed to it. It moves
+    /// on with `cancel` too, see `is_superseded`.
     pub request_id: usize,
+    /// The highest request id a request was sent with. Responses are only
+    /// taken from it, so that one arriving late can't overwrite a newer one.
+    /// It moves on with `cancel` too, like `request_id`.
+    pub latest_request_id: usize,
     pub status: CompletionStatus,
     pub offset: usize,
     pub buffer_id: BufferId,
     pub input: String,
     pub index: usize,
     pub input_items: im::HashMap<String, Arc<Vec<ScoredCompletionItem>>>,
-    empty: Arc<Vec<ScoredCompletionItem>>,
+    /// Words from the buffer, offered when no server provides any items
+    buffer_words: Arc<Vec<ScoredCompletionItem>>,
     pub filtered_items: Arc<Vec<ScoredCompletionItem>>,
     pub matcher: Arc<SkimMatcherV2>,
+    /// What `matcher` was built with, from the `completion-case-matching`
+    /// setting, see `set_case_matching`
+    pub case_matching: CaseMatching,
+    /// How the input is matched, from the `completion-match-strategy`
+    /// setting, see `set_match_strategy`
+    pub match_strategy: MatchStrategy,
+    /// The largest the popup gets, see `popup_size` for its actual size
+    /// and `preferred_sizes` for the size per language
     pub size: Size,
+    /// The width in characters of the widest row, label and detail
+    pub content_chars: usize,
+    /// Whether the items shown all have short labels and no detail, like
+    /// keywords, so that the popup can do without the icon column
+    compact_labels: bool,
+    /// The size of the documentation popup of the selected item
+    pub documentation_size: Size,
+    /// The size of the documentation popup once it's expanded, for
+    /// documentation too long for `documentation_size`
+    pub expanded_documentation_size: Size,
+    /// Whether the documentation popup is expanded. It's collapsed again
+    /// when the session ends.
+    pub documentation_expanded: bool,
+    /// How far the documentation of the selected item was scrolled down
+    /// with the keyboard, along with the session, input and index of that
+    /// selection, so that any other selection starts at the top
+    documentation_scroll: Option<((usize, String, usize), f64)>,
+    /// The items, by their response index and label, that a
+    /// `completionItem/resolve` request was sent for in this session
+    pub resolved: im::HashSet<(usize, String)>,
+    /// The item kinds that are hidden from the list. This isn't cleared
+    /// on cancel, so it's kept across the completion sessions of a tab.
+    pub suppressed_kinds: Vec<CompletionItemKind>,
+    /// The item kinds that rank above all others, in this order, from the
+    /// `completion-kind-priority` setting. The kind comes before the
+    /// score, so an item of a listed kind outranks a better match of
+    /// another kind. When empty, the kind doesn't affect the ranking.
+    pub kind_priority: Vec<CompletionItemKind>,
+    /// Whether moving past either end of the list wraps around
+    pub wrap: bool,
+    /// Whether the input is also matched against the item's detail
+    pub match_detail: bool,
+    /// The score an item has to reach per input character after the first
+    /// to be kept, so that weak matches of long inputs are dropped
+    pub min_score_per_char: i64,
+    /// Whether the last response said its list is incomplete, in which case
+    /// the server is asked again as the input changes instead of the items
+    /// being filtered locally
+    pub is_incomplete: bool,
+    /// The most items `filtered_items` is cut down to
+    pub max_items: usize,
+    /// How many items were cut from `filtered_items` by `max_items`
+    pub truncated: usize,
+    /// How much of the input the top item's label accounts for, from 0 to
+    /// 1, see `match_completeness`. Well below 1 means part of the input
+    /// only matched what isn't shown, like the filter text or the detail,
+    /// which hints at a typo. `None` without input or items.
+    pub match_completeness: Option<f64>,
+    /// The most items kept from a single response, the ones that sort
+    /// first, so that a server returning an enormous list doesn't have all
+    /// of it held on to and filtered on every keystroke
+    pub max_stored_items: usize,
+    /// How many items the last response had past `max_stored_items`, which
+    /// were dropped without ever being shown
+    pub source_capped: usize,
+    /// The fewest items a response needs for them to be scored on another
+    /// thread by `filter_in_background`, or `None` to always score them
+    /// right away
+    pub background_filter_min: Option<usize>,
+    /// Whether `filtered_items` is out of date, waiting for the result of
+    /// `filter_in_background`
+    filter_pending: bool,
+    /// Why the items are requested: how the session was started, or, once
+    /// a response said its list is incomplete, to complete that list
+    pub trigger_kind: CompletionTriggerKind,
+    /// The character that started the session, if it was started by one
+    /// and the items are requested for that
+    pub trigger_character: Option<String>,
+    /// The characters the language servers asked to trigger completion
+    /// on, per buffer
+    pub trigger_characters: im::HashMap<BufferId, Vec<String>>,
+    /// The language of the buffer the session was started in
+    pub language: Option<LapceLanguage>,
+    /// The recently accepted labels per language, most recent last.
+    /// Like `suppressed_kinds`, it's kept across sessions.
+    pub recent: im::HashMap<Option<LapceLanguage>, Vec<String>>,
+    /// When the requests of the session still waiting for a response were
+    /// sent, by their request id and input
+    pub sent_requests: im::HashMap<(usize, String), Instant>,
+    /// The latest round-trip latencies of completion requests per
+    /// language, oldest first. Kept across sessions like `recent`.
+    pub latencies: im::HashMap<Option<LapceLanguage>, Vec<Duration>>,
+    /// From the `completion-display-mode` setting
+    pub display_mode: CompletionDisplayMode,
+    /// Whether the server an item came from is shown on its row, which is
+    /// only done when the items of the session came from several servers
+    pub show_source: bool,
+    /// The score bonus for every matched character that starts a word of
+    /// the label, like the `B` of `fooBar` or the `b` of `foo_bar`, on top
+    /// of what the matcher already gives, from the
+    /// `completion-boundary-bonus` setting
+    pub boundary_bonus: i64,
+    /// The largest the popup gets per language, in place of `size`. Like
+    /// `recent`, it's kept across sessions.
+    pub preferred_sizes: im::HashMap<Option<LapceLanguage>, Size>,
+    /// Whether the popup stays up with a "No matches" row when nothing
+    /// matches the input, instead of disappearing
+    pub show_no_matches: bool,
+    /// The most rows the popup shows before the rest are scrolled to, from
+    /// the `completion-max-visible-rows` setting. It caps the height on
+    /// top of `max_size`, and zero leaves it to that alone.
+    pub max_visible_rows: usize,
+    /// Whether moving the selection scrolls the list to keep it in the
+    /// middle, rather than just enough to reveal it at the edge
+    pub center_selection: bool,
+    /// Whether the kind of an item is shown as an icon, from the
+    /// `completion-kind-icons` setting, or otherwise as its `kind_tag`
+    pub show_kind_icons: bool,
+    /// Whether the popup drops the icon column for short keyword-like
+    /// items, from the `completion-auto-compact` setting, see `is_compact`
+    pub auto_compact: bool,
+    /// Whether the matched characters of the labels are painted bold and
+    /// in the match color, from the `completion-match-highlight` setting
+    pub highlight_matches: bool,
+    /// Whether accepting a function or method appends `()`, from the
+    /// `completion-insert-parentheses` setting, see
+    /// `CompletionEdit::with_parentheses`
+    pub insert_parentheses: bool,
+    /// Whether an item that's the only match and exactly the input is
+    /// accepted without waiting for Enter, from the
+    /// `completion-auto-accept-exact` setting, see `auto_accept_pending`
+    pub auto_accept_exact: bool,
+    /// Whether the last input left a sole exact match to accept right away
+    auto_accept: bool,
+    /// Whether a member accessor typed right after accepting a method or
+    /// field opens completion again, from the
+    /// `completion-chain-after-accept` setting, see `accepted_member`
+    pub chain_after_accept: bool,
+    /// The buffer and the offset the last accepted member ended at, while
+    /// nothing else happened since, see `chained_trigger`
+    chained_at: Option<(BufferId, usize)>,
+    /// Called once every time an item is accepted, but not on cancel
+    on_accept: Option<CompletionAcceptCallback>,
+    /// Called with the description of the selected item when the
+    /// selection changes, see `announce_selection`
+    on_announce: Option<CompletionAnnounceCallback>,
+    /// How long the popup takes to fade in when it shows up and out when
+    /// it goes away, from the `completion-fade-duration` setting. Zero
+    /// shows and hides it at once.
+    pub fade_duration: Duration,
+    /// The opacity of the popup, moved towards that of its visibility by
+    /// `animate` on every animation frame
+    alpha: f64,
+    fade: Option<Fade>,
+    /// The response coming in chunks, until its last one
+    stream: Option<CompletionStream>,
+}
+
+/// A response coming in chunks, see `CompletionData::receive_chunk`
+#[derive(Clone)]
+struct CompletionStream {
+    request_id: usize,
+    input: String,
+    /// How many items the chunks so far had
+    received: usize,
+}
+
+/// A fade of the popup from one opacity to another
+#[derive(Clone, Copy, PartialEq)]
+struct Fade {
+    start: Instant,
+    from: f64,
+    to: f64,
 }
 
 impl CompletionData {
@@ -255,7 +648,10 @@ impl CompletionData {
         Self {
             id: WidgetId::next(),
             scroll_id: WidgetId::next(),
+            documentation_id: WidgetId::next(),
+            documentation_scroll_id: WidgetId::next(),
             request_id: 0,
+            latest_request_id: 0,
             index: 0,
             offset: 0,
             status: CompletionStatus::Inactive,
@@ -263,12 +659,88 @@ impl CompletionData {
             input: "".to_string(),
             input_items: im::HashMap::new(),
             filtered_items: Arc::new(Vec::new()),
-            matcher: Arc::new(SkimMatcherV2::default().ignore_case()),
-            size: Size::new(400.0, 300.0),
-            empty: Arc::new(Vec::new()),
+            matcher: Arc::new(CaseMatching::Ignore.matcher()),
+            case_matching: CaseMatching::Ignore,
+            match_strategy: MatchStrategy::Fuzzy,
+            size: Size::new(600.0, 300.0),
+            content_chars: 0,
+            compact_labels: false,
+            documentation_size: Size::new(400.0, 300.0),
+            expanded_documentation_size: Size::new(600.0, 600.0),
+            documentation_expanded: false,
+            documentation_scroll: None,
+            resolved: im::HashSet::new(),
+            suppressed_kinds: Vec::new(),
+            kind_priority: Vec::new(),
+            wrap: true,
+            match_detail: false,
+            min_score_per_char: 0,
+            is_incomplete: false,
+            max_items: 200,
+            truncated: 0,
+            match_completeness: None,
+            max_stored_items: 5000,
+            source_capped: 0,
+            background_filter_min: None,
+            filter_pending: false,
+            trigger_kind: CompletionTriggerKind::Invoked,
+            trigger_character: None,
+            trigger_characters: im::HashMap::new(),
+            language: None,
+            recent: im::HashMap::new(),
+            sent_requests: im::HashMap::new(),
+            latencies: im::HashMap::new(),
+            display_mode: CompletionDisplayMode::Popup,
+            show_source: true,
+            boundary_bonus: 0,
+            preferred_sizes: im::HashMap::new(),
+            show_no_matches: false,
+            max_visible_rows: 15,
+            center_selection: false,
+            show_kind_icons: true,
+            auto_compact: true,
+            highlight_matches: true,
+            insert_parentheses: false,
+            auto_accept_exact: false,
+            auto_accept: false,
+            chain_after_accept: false,
+            chained_at: None,
+            on_accept: None,
+            on_announce: None,
+            buffer_words: Arc::new(Vec::new()),
+            fade_duration: Duration::from_millis(80),
+            alpha: 0.0,
+            fade: None,
+            stream: None,
         }
     }
 
+    /// Take up the completion settings of `config`
+    pub fn update_config(&mut self, config: &Config) {
+        self.highlight_matches = config.editor.completion_match_highlight;
+        self.show_kind_icons = config.editor.completion_kind_icons;
+        self.auto_compact = config.editor.completion_auto_compact;
+        self.fade_duration =
+            Duration::from_millis(config.editor.completion_fade_duration as u64);
+        self.max_stored_items = config.editor.completion_max_stored_items;
+        self.max_visible_rows = config.editor.completion_max_visible_rows;
+        self.insert_parentheses = config.editor.completion_insert_parentheses;
+        self.auto_accept_exact = config.editor.completion_auto_accept_exact;
+        self.chain_after_accept = config.editor.completion_chain_after_accept;
+        self.kind_priority =
+            parse_kind_priority(&config.editor.completion_kind_priority);
+        self.set_display_mode(CompletionDisplayMode::from_setting(
+            &config.editor.completion_display_mode,
+        ));
+        self.set_case_matching(CaseMatching::from_setting(
+            &config.editor.completion_case_matching,
+        ));
+        self.set_match_strategy(MatchStrategy::from_setting(
+            &config.editor.completion_match_strategy,
+        ));
+        self.set_boundary_bonus(config.editor.completion_boundary_bonus as i64);
+    }
+
     pub fn len(&self) -> usize {
         self.current_items().len()
     }
@@ -277,16 +749,270 @@ impl CompletionData {
         self.len() == 0
     }
 
+    /// Where the selection is in the list, like `3/128`, or `None` when
+    /// there's at most a single item and nothing to orient in
+    pub fn position(&self) -> Option<String> {
+        let len = self.len();
+        if len <= 1 {
+            return None;
+        }
+        Some(format!("{}/{}", self.index + 1, len))
+    }
+
+    /// Rebuild the matcher for `case_matching` and re-filter the items
+    pub fn set_case_matching(&mut self, case_matching: CaseMatching) {
+        if self.case_matching == case_matching {
+            return;
+        }
+        self.case_matching = case_matching;
+        self.matcher = Arc::new(case_matching.matcher());
+        self.forget_scores();
+        self.filter_items();
+    }
+
+    pub fn set_min_score_per_char(&mut self, min_score_per_char: i64) {
+        self.min_score_per_char = min_score_per_char;
+        self.filter_items();
+    }
+
+    pub fn set_boundary_bonus(&mut self, boundary_bonus: i64) {
+        if self.boundary_bonus == boundary_bonus {
+            return;
+        }
+        self.boundary_bonus = boundary_bonus;
+        self.filter_items();
+    }
+
+    /// Match the input with `match_strategy` and re-filter the items
+    pub fn set_match_strategy(&mut self, match_strategy: MatchStrategy) {
+        if self.match_strategy == match_strategy {
+            return;
+        }
+        self.match_strategy = match_strategy;
+        self.forget_scores();
+        self.filter_items();
+    }
+
+    /// Drop the cached scores of the items, for when they'd be scored
+    /// differently now
+    fn forget_scores(&mut self) {
+        let input_items = self.input_items.iter_mut().map(|(_, items)| items);
+        for items in input_items.chain(std::iter::once(&mut self.buffer_words)) {
+            for item in Arc::make_mut(items).iter_mut() {
+                item.scored_input = None;
+            }
+        }
+    }
+
+    /// The lowest score of a kept item. It grows with the input, so that
+    /// a single character still matches everything it can.
+    fn min_score(&self) -> i64 {
+        let chars = self.input.chars().count() as i64;
+        self.min_score_per_char * (chars - 1).max(0)
+    }
+
+    pub fn set_display_mode(&mut self, display_mode: CompletionDisplayMode) {
+        self.display_mode = display_mode;
+    }
+
+    /// The items shown in the inline display mode: the page of
+    /// `INLINE_ITEMS` the selected item is on
+    pub fn inline_range(&self) -> Range<usize> {
+        let start = self.index / INLINE_ITEMS * INLINE_ITEMS;
+        start..(start + INLINE_ITEMS).min(self.len())
+    }
+
+    /// The row at `y` in the popup, if there's an item on it
+    pub fn row_at(&self, y: f64, line_height: f64) -> Option<usize> {
+        if y < 0.0 {
+            return None;
+        }
+        let row = (y / line_height).floor() as usize;
+        if row < self.len() {
+            Some(row)
+        } else {
+            None
+        }
+    }
+
+    /// The vertical span of the list to scroll into view for the selected
+    /// row. It's the row itself, or with `center_selection` a viewport
+    /// around it, so that the row ends up in the middle unless that would
+    /// scroll past either end of the list.
+    pub fn selection_span(
+        &self,
+        line_height: f64,
+        viewport_height: f64,
+    ) -> (f64, f64) {
+        let y = self.index as f64 * line_height;
+        if !self.center_selection || viewport_height <= line_height {
+            return (y, y + line_height);
+        }
+
+        let mut rows = self.len();
+        if self.more_label().is_some() {
+            rows += 1;
+        }
+        let content_height = rows as f64 * line_height;
+        let y1 = (y + (line_height + viewport_height) / 2.0)
+            .min(content_height)
+            .max(viewport_height);
+        let y0 = (y1 - viewport_height).max(0.0);
+        (y0, y1)
+    }
+
+    /// Select the item at `index`, or the last one if it's past the end
+    pub fn select(&mut self, index: usize) {
+        self.index = index.min(self.len().saturating_sub(1));
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Select the next item, wrapping around to the first one from the
+    /// last if `wrap` is set. Nothing happens with no items.
     pub fn next(&mut self) {
-        self.index = Movement::Down.update_index(self.index, self.len(), 1, true);
+        if self.is_empty() {
+            return;
+        }
+        self.index =
+            Movement::Down.update_index(self.index, self.len(), 1, self.wrap);
     }
 
+    /// Select the previous item, wrapping around to the last one from the
+    /// first if `wrap` is set. Nothing happens with no items.
     pub fn previous(&mut self) {
-        self.index = Movement::Up.update_index(self.index, self.len(), 1, true);
+        if self.is_empty() {
+            return;
+        }
+        self.index = Movement::Up.update_index(self.index, self.len(), 1, self.wrap);
+    }
+
+    /// Select the first item
+    pub fn first(&mut self) {
+        self.index = 0;
+    }
+
+    /// Select the last item
+    pub fn last(&mut self) {
+        self.index = self.len().saturating_sub(1);
+    }
+
+    /// Remember the largest size of the popup for `language`
+    pub fn set_preferred_size(
+        &mut self,
+        language: Option<LapceLanguage>,
+        size: Size,
+    ) {
+        self.preferred_sizes.insert(language, size);
+    }
+
+    /// The largest the popup gets in this session: the preferred size for
+    /// the language of the session, or `size` if none was set
+    pub fn max_size(&self) -> Size {
+        self.preferred_sizes
+            .get(&self.language)
+            .copied()
+            .unwrap_or(self.size)
+    }
+
+    /// Whether the popup looks different than it did with `old`, so that
+    /// it has to be laid out and painted again. Rendering depends on:
+    ///
+    /// - the session, `status` and `request_id`
+    /// - the items shown and their order, `current_items`, and the
+    ///   selection among them, `index`
+    /// - the `input`, which the matched characters are highlighted for
+    /// - the rows below the items, for `truncated`, `source_capped` and
+    ///   `shows_no_matches`
+    /// - the size of the popup, from `content_chars`, `max_size`,
+    ///   `max_visible_rows` and `icon_width`
+    /// - the display options, `display_mode`, `show_source`,
+    ///   `show_no_matches`, `highlight_matches` and `show_kind_icons`
+    ///
+    /// A field that changes how the popup looks belongs here too.
+    pub fn should_repaint(&self, old: &Self) -> bool {
+        self.status != old.status
+            || self.request_id != old.request_id
+            || !Arc::ptr_eq(self.current_items(), old.current_items())
+            || !Arc::ptr_eq(&self.filtered_items, &old.filtered_items)
+            || self.index != old.index
+            || self.input != old.input
+            || self.truncated != old.truncated
+            || self.source_capped != old.source_capped
+            || self.content_chars != old.content_chars
+            || self.is_compact() != old.is_compact()
+            || self.max_size() != old.max_size()
+            || self.max_visible_rows != old.max_visible_rows
+            || self.display_mode != old.display_mode
+            || self.show_source != old.show_source
+            || self.show_no_matches != old.show_no_matches
+            || self.highlight_matches != old.highlight_matches
+            || self.show_kind_icons != old.show_kind_icons
+    }
+
+    /// Expand the documentation popup, or collapse it if it's expanded
+    pub fn toggle_documentation(&mut self) {
+        self.documentation_expanded = !self.documentation_expanded;
+    }
+
+    /// How far the documentation of the selected item is scrolled down
+    pub fn documentation_scroll(&self) -> f64 {
+        match &self.documentation_scroll {
+            Some((selection, offset)) if *selection == self.selection_key() => {
+                *offset
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Scroll the documentation of the selected item by `delta`, keeping
+    /// within `0.0..=max`, the height of the content past the popup
+    pub fn scroll_documentation(&mut self, delta: f64, max: f64) {
+        let offset = (self.documentation_scroll() + delta).clamp(0.0, max.max(0.0));
+        self.documentation_scroll = Some((self.selection_key(), offset));
+    }
+
+    fn selection_key(&self) -> (usize, String, usize) {
+        (self.request_id, self.input.clone(), self.index)
+    }
+
+    /// The size of the documentation popup, larger while it's expanded
+    pub fn documentation_box_size(&self) -> Size {
+        if self.documentation_expanded {
+            self.expanded_documentation_size
+        } else {
+            self.documentation_size
+        }
+    }
+
+    /// The number of rows that fit in the popup
+    fn visible_rows(&self, line_height: f64) -> usize {
+        let rows = (self.max_size().height / line_height).floor() as usize;
+        let rows = if self.max_visible_rows > 0 {
+            rows.min(self.max_visible_rows)
+        } else {
+            rows
+        };
+        rows.max(1)
+    }
+
+    /// Move the selection down by a page, without wrapping around
+    pub fn page_next(&mut self, line_height: f64) {
+        let rows = self.visible_rows(line_height);
+        self.index =
+            Movement::Down.update_index(self.index, self.len(), rows, false);
+    }
+
+    /// Move the selection up by a page, without wrapping around
+    pub fn page_previous(&mut self, line_height: f64) {
+        let rows = self.visible_rows(line_height);
+        self.index = Movement::Up.update_index(self.index, self.len(), rows, false);
     }
 
     pub fn current_items(&self) -> &Arc<Vec<ScoredCompletionItem>> {
-        if self.input.is_empty() {
+        if self.input.is_empty() && self.suppressed_kinds.is_empty() {
             self.all_items()
         } else {
             &self.filtered_items
@@ -297,20 +1023,240 @@ impl CompletionData {
         self.input_items
             .get(&self.input)
             .filter(|items| !items.is_empty())
-            .unwrap_or_else(move || self.input_items.get("").unwrap_or(&self.empty))
+            .or_else(|| self.input_items.get("").filter(|items| !items.is_empty()))
+            .unwrap_or(&self.buffer_words)
+    }
+
+    pub fn current_item(&self) -> &ScoredCompletionItem {
+        &self.current_items()[self.index]
+    }
+
+    /// The match indices of the selected item, empty if nothing is selected
+    pub fn current_indices(&self) -> &[usize] {
+        self.current_items()
+            .get(self.index)
+            .map(|item| item.indices())
+            .unwrap_or(&[])
+    }
+
+    /// Whether the documentation popup is shown, which is only while the
+    /// list is and the selected item has documentation
+    pub fn shows_documentation(&self) -> bool {
+        self.is_visible() && self.current_documentation().is_some()
+    }
+
+    /// The documentation of the selected item, if it has any
+    pub fn current_documentation(&self) -> Option<&Documentation> {
+        let item = self.current_items().get(self.index)?;
+        let documentation = item.item.documentation.as_ref()?;
+        let text = match documentation {
+            Documentation::String(text) => text,
+            Documentation::MarkupContent(content) => &content.value,
+        };
+        if text.trim().is_empty() {
+            return None;
+        }
+        Some(documentation)
+    }
+
+    /// The `commit_characters` of the selected item
+    pub fn current_commit_characters(&self) -> &[String] {
+        self.current_items()
+            .get(self.index)
+            .and_then(|item| item.item.commit_characters.as_deref())
+            .unwrap_or(&[])
+    }
+
+    /// Whether typing `c` should accept the selected item before
+    /// inserting `c` itself
+    pub fn is_commit_character(&self, c: &str) -> bool {
+        self.status != CompletionStatus::Inactive
+            && self
+                .current_commit_characters()
+                .iter()
+                .any(|commit| commit == c)
+    }
+
+    /// The rows of the popup, one per current item
+    pub fn rows(&self) -> impl Iterator<Item = CompletionRow<'_>> {
+        let shows_sources = self.shows_sources();
+        self.current_items()
+            .iter()
+            .enumerate()
+            .map(move |(i, item)| CompletionRow {
+                label: item.label(),
+                label_detail: item.label_detail(),
+                detail: item.detail(),
+                source: item.source.as_deref().filter(|_| shows_sources),
+                kind: item.item.kind,
+                color: item.color(),
+                deprecated: item.is_deprecated(),
+                indices: item.indices(),
+                selected: i == self.index,
+            })
+    }
+
+    /// Whether the source tag is drawn on the rows, which it is only when
+    /// `show_source` is set and more than one server contributed items
+    pub fn shows_sources(&self) -> bool {
+        if !self.show_source {
+            return false;
+        }
+        let mut sources = self.all_items().iter().filter_map(|i| i.source.as_ref());
+        match sources.next() {
+            Some(first) => sources.any(|source| source != first),
+            None => false,
+        }
+    }
+
+    /// Whether a cursor at `offset` in `buffer_id` is still where the
+    /// session was started, somewhere within the input typed since
+    pub fn is_at(&self, buffer_id: BufferId, offset: usize) -> bool {
+        buffer_id == self.buffer_id
+            && offset >= self.offset
+            && offset <= self.offset + self.input.len()
+    }
+
+    /// Whether a cursor at `offset` in `buffer_id` went back before where
+    /// the session was started, like by deleting its trigger character,
+    /// which ends it rather than leaving its items up for what's before
+    pub fn is_behind(&self, buffer_id: BufferId, offset: usize) -> bool {
+        self.status != CompletionStatus::Inactive
+            && buffer_id == self.buffer_id
+            && offset < self.offset
+    }
+
+    /// Whether the popup is drawn: a session is going on and it has items
+    /// to show, which it doesn't while the first response is in flight
+    pub fn is_visible(&self) -> bool {
+        self.status != CompletionStatus::Inactive
+            && (!self.is_empty() || self.shows_no_matches())
+    }
+
+    /// The opacity the popup is painted with, which is only ever between
+    /// 0 and 1 while it fades in or out. Keys don't wait for the fade, an
+    /// item can be accepted as soon as the popup has it.
+    pub fn alpha(&self) -> f64 {
+        if self.fade_duration.is_zero() {
+            self.target_alpha()
+        } else {
+            self.alpha
+        }
+    }
+
+    fn target_alpha(&self) -> f64 {
+        if self.is_visible() {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether the popup is fading in or out, and so needs `animate`
+    /// called on the next animation frame
+    pub fn is_fading(&self) -> bool {
+        self.alpha() != self.target_alpha()
+    }
+
+    /// Whether the popup went away but is still painted while fading out
+    pub fn is_fading_out(&self) -> bool {
+        !self.is_visible() && self.alpha() > 0.0
+    }
+
+    /// Move the opacity towards that of the visibility of the popup, for
+    /// the animation frame at `now`. Returns whether the fade goes on.
+    pub fn animate(&mut self, now: Instant) -> bool {
+        let target = self.target_alpha();
+        if self.fade_duration.is_zero() {
+            self.alpha = target;
+            self.fade = None;
+            return false;
+        }
+        // A popup showing up again while fading out fades back in from
+        // where it is
+        let fade = match self.fade {
+            Some(fade) if fade.to == target => fade,
+            _ => Fade {
+                start: now,
+                from: self.alpha,
+                to: target,
+            },
+        };
+        let progress = now.saturating_duration_since(fade.start).as_secs_f64()
+            / self.fade_duration.as_secs_f64();
+        if progress >= 1.0 {
+            self.alpha = target;
+            self.fade = None;
+            false
+        } else {
+            self.alpha = fade.from + (fade.to - fade.from) * progress;
+            self.fade = Some(fade);
+            true
+        }
+    }
+
+    /// Whether the popup is showing items, so that keys like Tab should
+    /// accept the selected one rather than do what they normally do
+    pub fn is_active_and_nonempty(&self) -> bool {
+        self.status != CompletionStatus::Inactive && !self.is_empty()
     }
 
-    pub fn current_item(&self) -> &CompletionItem {
-        &self.current_items()[self.index].item
+    /// The text of the row below the items telling how many were left
+    /// out, by `max_items` or by `max_stored_items`, if any were
+    pub fn more_label(&self) -> Option<String> {
+        // With nothing left to show, there's the "No matches" row instead
+        if self.is_empty() {
+            return None;
+        }
+        match (self.truncated, self.source_capped) {
+            (0, 0) => None,
+            (truncated, 0) => Some(format!("… {} more", truncated)),
+            (0, capped) => Some(format!("… {} not loaded", capped)),
+            (truncated, capped) => {
+                Some(format!("… {} more, {} not loaded", truncated, capped))
+            }
+        }
     }
 
+    /// Whether the popup shows the "No matches" row, which it does when
+    /// `show_no_matches` is set and there were items, but none match the
+    /// input. The row can't be selected or accepted.
+    pub fn shows_no_matches(&self) -> bool {
+        self.show_no_matches
+            && self.status != CompletionStatus::Inactive
+            && !self.input.is_empty()
+            && self.is_empty()
+            && !self.all_items().is_empty()
+    }
+
+    /// The label of the selected item, empty if nothing is selected
     pub fn current(&self) -> &str {
-        self.current_items()[self.index].item.label.as_str()
+        self.current_items()
+            .get(self.index)
+            .map(|item| item.item.label.as_str())
+            .unwrap_or("")
+    }
+
+    /// The context sent along with the completion requests, which tells the
+    /// server why it's asked, since servers rank and filter differently
+    /// for each reason
+    pub fn context(&self) -> CompletionContext {
+        CompletionContext {
+            trigger_kind: self.trigger_kind,
+            trigger_character: self.trigger_character.clone(),
+        }
+    }
+
+    /// Record that the items are requested again because the last list
+    /// was incomplete, rather than for what started the session
+    pub fn refetch_incomplete(&mut self) {
+        self.trigger_kind = CompletionTriggerKind::TriggerForIncompleteCompletions;
+        self.trigger_character = None;
     }
 
     #[allow(clippy::too_many_arguments)]
     pub fn request(
-        &self,
+        &mut self,
         proxy: Arc<LapceProxy>,
         request_id: usize,
         buffer_id: BufferId,
@@ -319,19 +1265,27 @@ impl CompletionData {
         completion_widget_id: WidgetId,
         event_sink: ExtEventSink,
     ) {
+        let context = self.context();
+        self.latest_request_id = self.latest_request_id.max(request_id);
+        self.sent_requests
+            .insert((request_id, input.clone()), Instant::now());
         proxy.get_completion(
             request_id,
             buffer_id,
             position,
+            Some(context),
             Box::new(move |result| {
                 if let Ok(res) = result {
                     if let Ok(resp) =
-                        serde_json::from_value::<CompletionResponse>(res)
+                        serde_json::from_value::<GetCompletionResponse>(res)
                     {
                         let _ = event_sink.submit_command(
                             LAPCE_UI_COMMAND,
                             LapceUICommand::UpdateCompletion(
-                                request_id, input, resp,
+                                request_id,
+                                input,
+                                resp.response,
+                                Some(resp.source),
                             ),
                             Target::Widget(completion_widget_id),
                         );
@@ -341,144 +1295,4455 @@ impl CompletionData {
         );
     }
 
-    pub fn cancel(&mut self) {
-        if self.status == CompletionStatus::Inactive {
-            return;
-        }
-        self.status = CompletionStatus::Inactive;
-        self.input = "".to_string();
-        self.input_items.clear();
-        self.index = 0;
+    /// Complete the file name being typed in a string literal from the
+    /// entries of `dir`, in place of asking the language server
+    pub fn request_paths(
+        &mut self,
+        proxy: Arc<LapceProxy>,
+        request_id: usize,
+        dir: PathBuf,
+        start: Position,
+        completion_widget_id: WidgetId,
+        event_sink: ExtEventSink,
+    ) {
+        self.latest_request_id = self.latest_request_id.max(request_id);
+        self.sent_requests
+            .insert((request_id, "".to_string()), Instant::now());
+        proxy.read_dir(
+            &dir,
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    if let Ok(resp) = serde_json::from_value::<ReadDirResponse>(res)
+                    {
+                        let entries = resp.items.values().filter_map(|item| {
+                            let name = item.path_buf.file_name()?;
+                            Some((name.to_string_lossy().to_string(), item.is_dir))
+                        });
+                        let items = path_items(entries, start);
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateCompletion(
+                                request_id,
+                                "".to_string(),
+                                CompletionResponse::Array(items),
+                                Some("path".to_string()),
+                            ),
+                            Target::Widget(completion_widget_id),
+                        );
+                    }
+                }
+            }),
+        );
     }
 
-    pub fn update_input(&mut self, input: String) {
-        self.input = input;
-        self.index = 0;
+    /// The item a `completionItem/resolve` request for the selected item is
+    /// sent with, which is the item as the server sent it. In particular
+    /// its opaque `data` goes back unchanged, as servers like rust-analyzer
+    /// need it to tell what to resolve.
+    pub fn resolve_item(&self) -> Option<CompletionItem> {
         if self.status == CompletionStatus::Inactive {
-            return;
+            return None;
         }
-        self.filter_items();
+        self.current_items()
+            .get(self.index)
+            .map(|item| item.item.clone())
     }
 
-    pub fn receive(
+    /// Send a `completionItem/resolve` request for the selected item,
+    /// if that hasn't been done already in this session, so that the
+    /// documentation and detail missing from the initial response are
+    /// filled in.
+    pub fn resolve_current(
         &mut self,
-        request_id: usize,
-        input: String,
-        resp: CompletionResponse,
+        proxy: Arc<LapceProxy>,
+        event_sink: ExtEventSink,
     ) {
-        if self.status == CompletionStatus::Inactive || self.request_id != request_id
-        {
+        if self.status == CompletionStatus::Inactive {
             return;
         }
-
-        let items = match resp {
-            CompletionResponse::Array(items) => items,
-            CompletionResponse::List(list) => list.items,
+        let item = match self.current_items().get(self.index) {
+            Some(item) => item,
+            None => return,
         };
-        let items: Vec<ScoredCompletionItem> = items
-            .iter()
-            .map(|i| ScoredCompletionItem {
-                item: i.to_owned(),
-                score: 0,
-                label_score: 0,
-                indices: Vec::new(),
-            })
-            .collect();
-
-        self.input_items.insert(input, Arc::new(items));
-        self.filter_items();
-    }
+        let key = (item.index, item.item.label.clone());
+        if self.resolved.contains(&key) {
+            return;
+        }
 
-    pub fn filter_items(&mut self) {
-        if self.input.is_empty() {
+        let request_id = self.request_id;
+        let index = item.index;
+        let completion_item = match self.resolve_item() {
+            Some(completion_item) => completion_item,
+            None => return,
+        };
+        let completion_widget_id = self.id;
+        self.resolved.insert(key);
+        proxy.completion_resolve(
+            self.buffer_id,
+            completion_item,
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    if let Ok(item) = serde_json::from_value::<CompletionItem>(res) {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateCompletionItem(
+                                request_id,
+                                index,
+                                Box::new(item),
+                            ),
+                            Target::Widget(completion_widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Merge the result of a `completionItem/resolve` request back into
+    /// the item at `index` of the server response.
+    pub fn receive_resolve(
+        &mut self,
+        request_id: usize,
+        index: usize,
+        resolved: CompletionItem,
+    ) {
+        if self.is_superseded(request_id) {
             return;
         }
 
-        let mut items: Vec<ScoredCompletionItem> = self
-            .all_items()
-            .iter()
-            .filter_map(|i| {
-                let filter_text =
-                    i.item.filter_text.as_ref().unwrap_or(&i.item.label);
-                let shift = i.item.label.match_indices(filter_text).next()?.0;
-                if let Some((score, mut indices)) =
-                    self.matcher.fuzzy_indices(filter_text, &self.input)
-                {
-                    if shift > 0 {
-                        for idx in indices.iter_mut() {
-                            *idx += shift;
-                        }
-                    }
-                    let mut item = i.clone();
-                    item.score = score;
-                    item.label_score = score;
-                    item.indices = indices;
-                    if let Some(score) =
-                        self.matcher.fuzzy_match(&i.item.label, &self.input)
-                    {
-                        item.label_score = score;
-                    }
-                    Some(item)
-                } else {
-                    None
+        let merge = |items: &mut Arc<Vec<ScoredCompletionItem>>| {
+            let is_match = |i: &ScoredCompletionItem| {
+                i.index == index && i.item.label == resolved.label
+            };
+            if !items.iter().any(is_match) {
+                return;
+            }
+            for i in Arc::make_mut(items).iter_mut().filter(|i| is_match(i)) {
+                if resolved.documentation.is_some() {
+                    i.item.documentation = resolved.documentation.clone();
                 }
+                if resolved.detail.is_some() {
+                    i.item.detail = resolved.detail.clone();
+                }
+                if resolved.additional_text_edits.is_some() {
+                    i.item.additional_text_edits =
+                        resolved.additional_text_edits.clone();
+                }
+            }
+        };
+        for (_, items) in self.input_items.iter_mut() {
+            merge(items);
+        }
+        merge(&mut self.filtered_items);
+    }
+
+    /// Whether `request_id` is of a session that was cancelled or
+    /// replaced since, so that what comes back for it is to be dropped
+    fn is_superseded(&self, request_id: usize) -> bool {
+        self.status == CompletionStatus::Inactive || request_id != self.request_id
+    }
+
+    pub fn cancel(&mut self) {
+        // Like moving the cursor away, which ends the chance to chain on
+        // an accepted member
+        self.chained_at = None;
+        if self.status == CompletionStatus::Inactive {
+            return;
+        }
+        self.status = CompletionStatus::Inactive;
+        // Resolves and background filtering still in flight for this
+        // session are dropped as they come back, even when the next
+        // session is started without a new request id
+        self.request_id += 1;
+        self.latest_request_id = self.latest_request_id.max(self.request_id);
+        self.input = "".to_string();
+        self.input_items.clear();
+        self.buffer_words = Arc::new(Vec::new());
+        self.resolved.clear();
+        self.sent_requests.clear();
+        self.truncated = 0;
+        self.match_completeness = None;
+        self.source_capped = 0;
+        self.filter_pending = false;
+        self.is_incomplete = false;
+        self.stream = None;
+        self.auto_accept = false;
+        self.documentation_expanded = false;
+        self.documentation_scroll = None;
+        self.index = 0;
+    }
+
+    /// Cancel the session and drop every item it still holds on to,
+    /// for when the buffer it was started in goes away
+    pub fn reset(&mut self) {
+        self.cancel();
+        self.input_items.clear();
+        self.filtered_items = Arc::new(Vec::new());
+        self.truncated = 0;
+        self.clamp_index();
+    }
+
+    pub fn update_input(&mut self, input: String) {
+        // Narrowing can't bring back the items cut by `max_items`, nor the
+        // ones below a `min_score` that a longer input may clear
+        let extends_input = !self.input.is_empty()
+            && input.starts_with(&self.input)
+            && self.truncated == 0
+            && self.min_score_per_char == 0
+            && !self.filter_pending;
+        let items = self.all_items().clone();
+        // The item the selection was moved to, which is kept selected if
+        // it still matches. When the selection wasn't moved off the top
+        // item, the new top item is selected instead.
+        let selected = if self.index > 0 {
+            self.current_items()
+                .get(self.index)
+                .map(|i| i.item.label.clone())
+        } else {
+            None
+        };
+        self.input = input;
+        self.index = 0;
+        self.auto_accept = false;
+        if self.status == CompletionStatus::Inactive {
+            return;
+        }
+        if self.is_incomplete {
+            // The items for the new input come with the next response
+            return;
+        }
+        let narrowed = extends_input
+            && !items.is_empty()
+            && Arc::ptr_eq(&items, self.all_items());
+        // Not held on to, so that `store_scores` doesn't copy the items
+        drop(items);
+        if narrowed {
+            self.narrow_items();
+        } else {
+            self.filter_items();
+        }
+
+        let index = selected.and_then(|label| {
+            self.current_items()
+                .iter()
+                .position(|i| i.item.label == label)
+        });
+        match index {
+            Some(index) if narrowed => self.index = index,
+            _ => self.select_preselected(),
+        }
+        self.auto_accept = self.auto_accept_exact && self.is_sole_exact_match();
+    }
+
+    /// Whether the input has narrowed the items down to a single one that
+    /// is the input, ignoring case. While the items aren't all there yet,
+    /// with a server response that's incomplete or still being filtered,
+    /// a longer match could still turn up, so it's not taken as sole.
+    fn is_sole_exact_match(&self) -> bool {
+        !self.input.is_empty()
+            && !self.is_incomplete
+            && !self.filter_pending
+            && !self.is_streaming()
+            && self.len() == 1
+            && self.current_items()[0].item.label.to_lowercase()
+                == self.input.to_lowercase()
+    }
+
+    /// Whether the selected item is to be accepted right away, as the sole
+    /// exact match of the input with `auto_accept_exact` on
+    pub fn auto_accept_pending(&self) -> bool {
+        self.status != CompletionStatus::Inactive && self.auto_accept
+    }
+
+    pub fn receive(
+        &mut self,
+        request_id: usize,
+        input: String,
+        resp: CompletionResponse,
+        source: Option<String>,
+    ) {
+        // A response that arrives after the session was cancelled, like
+        // with Escape, mustn't bring the popup back
+        if self.status == CompletionStatus::Inactive
+            || request_id < self.latest_request_id
+        {
+            return;
+        }
+
+        self.record_latency(request_id, &input);
+
+        let (items, is_incomplete) = match resp {
+            CompletionResponse::Array(items) => (items, false),
+            CompletionResponse::List(list) => (list.items, list.is_incomplete),
+        };
+        self.is_incomplete = is_incomplete;
+        let items = Self::scored_items(items, 0, source);
+        if self.store_items(input, items) {
+            self.select_preselected();
+        }
+    }
+
+    /// Take a part of a response that comes in chunks, for servers that
+    /// stream large results. The chunks of a request are merged, with the
+    /// duplicates across them dropped, and filtered as they come, so that
+    /// the popup shows the first items without waiting for the rest.
+    /// `is_last` marks the end of the stream, see `is_streaming`.
+    pub fn receive_chunk(
+        &mut self,
+        request_id: usize,
+        input: String,
+        items: Vec<CompletionItem>,
+        source: Option<String>,
+        is_last: bool,
+    ) {
+        // The chunks of a superseded request are as stale as its response
+        if self.status == CompletionStatus::Inactive
+            || request_id < self.latest_request_id
+        {
+            return;
+        }
+
+        self.record_latency(request_id, &input);
+
+        let streamed = match self.stream.as_ref() {
+            Some(stream)
+                if stream.request_id == request_id && stream.input == input =>
+            {
+                Some(stream.received)
+            }
+            _ => None,
+        };
+        // The items are numbered on from the earlier chunks, so that they
+        // keep the order of the whole response
+        let mut all_items = match streamed {
+            Some(_) => self
+                .input_items
+                .get(&input)
+                .map(|items| items.to_vec())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let count = items.len();
+        let received = streamed.unwrap_or(0);
+        all_items.extend(Self::scored_items(items, received, source));
+        self.stream = if is_last {
+            None
+        } else {
+            Some(CompletionStream {
+                request_id,
+                input: input.clone(),
+                received: received + count,
             })
-            .collect();
+        };
+
+        // The first chunk selects like a whole response would, and the
+        // later ones keep the selection where it is
+        let selected = if streamed.is_some() {
+            self.current_items()
+                .get(self.index)
+                .map(|i| i.item.label.clone())
+        } else {
+            None
+        };
+        if !self.store_items(input, all_items) {
+            return;
+        }
+        let index = selected.and_then(|label| {
+            self.current_items()
+                .iter()
+                .position(|i| i.item.label == label)
+        });
+        match index {
+            Some(index) => self.index = index,
+            None => self.select_preselected(),
+        }
+    }
+
+    /// Whether a response coming in chunks is still missing some, see
+    /// `receive_chunk`
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// The items of a response, numbered from `first_index`, with those
+    /// without a label dropped
+    fn scored_items(
+        items: Vec<CompletionItem>,
+        first_index: usize,
+        source: Option<String>,
+    ) -> Vec<ScoredCompletionItem> {
+        let source: Option<Arc<str>> = source.map(Arc::from);
+        items
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, i)| {
+                Some(
+                    ScoredCompletionItem::new(
+                        Self::with_label(i)?,
+                        first_index + index,
+                    )
+                    .with_source(source.clone()),
+                )
+            })
+            .collect()
+    }
+
+    /// Sort, deduplicate and cap the items received for `input`, then
+    /// filter them, unless that's left to `filter_in_background`. Returns
+    /// whether they were filtered.
+    fn store_items(
+        &mut self,
+        input: String,
+        mut items: Vec<ScoredCompletionItem>,
+    ) -> bool {
         items.sort_by(|a, b| {
-            b.score
-                .cmp(&a.score)
-                .then_with(|| b.label_score.cmp(&a.label_score))
-                .then_with(|| a.item.label.len().cmp(&b.item.label.len()))
+            kind_rank(&self.kind_priority, &a.item)
+                .cmp(&kind_rank(&self.kind_priority, &b.item))
+                .then_with(|| a.sort_text.cmp(&b.sort_text))
+                .then_with(|| a.index.cmp(&b.index))
         });
-        self.filtered_items = Arc::new(items);
+        Self::dedup_items(&mut items);
+        self.source_capped = items.len().saturating_sub(self.max_stored_items);
+        items.truncate(self.max_stored_items);
+
+        self.input_items.insert(input, Arc::new(items));
+        if self.filters_in_background() {
+            self.filter_pending = true;
+            return false;
+        }
+        self.filter_items();
+        true
     }
-}
 
-impl Default for CompletionData {
-    fn default() -> Self {
-        Self::new()
+    /// Whether the items received are too many to score right away, so
+    /// that it's left to `filter_in_background`
+    pub fn filters_in_background(&self) -> bool {
+        !self.input.is_empty()
+            && self
+                .background_filter_min
+                .map(|min| self.all_items().len() >= min)
+                .unwrap_or(false)
     }
-}
 
-pub struct CompletionNew {}
+    /// Whether the items received are waiting to be scored by
+    /// `filter_in_background`
+    pub fn is_filter_pending(&self) -> bool {
+        self.filter_pending
+    }
 
-impl CompletionNew {
-    pub fn new() -> Self {
-        Self {}
+    /// Score the items against the input on another thread, so that a big
+    /// list doesn't hold up the UI. The result comes back to the
+    /// completion widget as `UpdateFilteredCompletionItems`.
+    pub fn filter_in_background(
+        &self,
+        completion_widget_id: WidgetId,
+        event_sink: ExtEventSink,
+    ) {
+        let completion = self.clone();
+        std::thread::spawn(move || {
+            let items = completion.filter(completion.all_items());
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::UpdateFilteredCompletionItems(
+                    completion.request_id,
+                    completion.input,
+                    items,
+                ),
+                Target::Widget(completion_widget_id),
+            );
+        });
     }
-}
 
-impl Default for CompletionNew {
-    fn default() -> Self {
-        Self::new()
+    /// Take the items scored by `filter_in_background`, unless the session
+    /// or the input changed since, which filters the items again anyway
+    pub fn receive_filtered(
+        &mut self,
+        request_id: usize,
+        input: &str,
+        items: Vec<ScoredCompletionItem>,
+    ) {
+        if !self.filter_pending
+            || self.is_superseded(request_id)
+            || input != self.input
+        {
+            return;
+        }
+        self.set_filtered_items(items);
+        self.select_preselected();
     }
-}
 
-#[derive(Clone)]
-pub struct ScoredCompletionItem {
-    pub item: CompletionItem,
+    /// Set the words offered while no server has provided any items,
+    /// see `buffer_words`
+    pub fn set_buffer_words(&mut self, words: Vec<CompletionItem>) {
+        let source: Arc<str> = Arc::from("buffer");
+        self.buffer_words = Arc::new(
+            words
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    ScoredCompletionItem::new(item, index)
+                        .with_source(Some(source.clone()))
+                })
+                .collect(),
+        );
+        self.filter_items();
+    }
 
-    pub score: i64,
-    pub label_score: i64,
-    pub indices: Vec<usize>,
-}
+    /// Add items that didn't come from a language server, e.g. from a plugin,
+    /// to the session started by `request_id`. They're deduplicated and
+    /// scored together with the items already received.
+    pub fn merge_items(
+        &mut self,
+        request_id: usize,
+        items: Vec<CompletionItem>,
+        source: Option<String>,
+    ) {
+        if self.is_superseded(request_id) {
+            return;
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let source: Option<Arc<str>> = source.map(Arc::from);
+        let merged: Vec<CompletionItem> =
+            items.into_iter().filter_map(Self::with_label).collect();
+        if merged.is_empty() {
+            return;
+        }
 
-    #[test]
-    fn test_snippet() {
-        let s = "start $1${2:second ${3:third}} $0";
-        let parsed = Snippet::from_str(s).unwrap();
-        assert_eq!(s, parsed.to_string());
+        if !self.input_items.contains_key("") {
+            self.input_items
+                .insert("".to_string(), Arc::new(Vec::new()));
+        }
+        for (_, items) in self.input_items.iter_mut() {
+            let items = Arc::make_mut(items);
+            let start = items.iter().map(|i| i.index + 1).max().unwrap_or(0);
+            items.extend(merged.iter().cloned().enumerate().map(|(i, item)| {
+                ScoredCompletionItem::new(item, start + i)
+                    .with_source(source.clone())
+            }));
+            items.sort_by(|a, b| {
+                kind_rank(&self.kind_priority, &a.item)
+                    .cmp(&kind_rank(&self.kind_priority, &b.item))
+                    .then_with(|| a.sort_text.cmp(&b.sort_text))
+                    .then_with(|| a.index.cmp(&b.index))
+            });
+            Self::dedup_items(items);
+        }
 
-        let text = "start second third ";
-        assert_eq!(text, parsed.text());
+        let current = self.current_items().get(self.index).map(|i| i.index);
+        self.filter_items();
+        if let Some(index) = current.and_then(|current| {
+            self.current_items().iter().position(|i| i.index == current)
+        }) {
+            self.index = index;
+        }
+    }
 
-        assert_eq!(
-            vec![(1, (6, 6)), (2, (6, 18)), (3, (13, 18)), (0, (19, 19))],
-            parsed.tabs(0)
+    /// The edits accepting the selected item with the cursors at `offsets`
+    /// makes, see `accept_item`. They're empty when there's no item to
+    /// accept.
+    pub fn accept(&self, buffer: &Buffer, offsets: &[usize]) -> Vec<CompletionEdit> {
+        if self.status == CompletionStatus::Inactive {
+            return Vec::new();
+        }
+        match self.current_items().get(self.index) {
+            Some(item) => {
+                self.accept_item(&item.item, buffer, offsets, self.language)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The edits accepting `item` with the cursors at `offsets` makes in a
+    /// buffer of `language`, see `CompletionEdit::at_cursors`. The item
+    /// doesn't need to be in the list, since it may have been resolved
+    /// after the session ended.
+    pub fn accept_item(
+        &self,
+        item: &CompletionItem,
+        buffer: &Buffer,
+        offsets: &[usize],
+        language: Option<LapceLanguage>,
+    ) -> Vec<CompletionEdit> {
+        let word_chars = language
+            .map(|language| language.word_chars())
+            .unwrap_or_default();
+        CompletionEdit::at_cursors(item, buffer, offsets, word_chars)
+            .into_iter()
+            .map(|edit| {
+                if self.insert_parentheses {
+                    edit.with_parentheses(item, buffer)
+                } else {
+                    edit
+                }
+            })
+            .collect()
+    }
+
+    /// Record how long the response to the request took, and forget the
+    /// requests of earlier sessions, whose responses are ignored anyway
+    fn record_latency(&mut self, request_id: usize, input: &str) {
+        self.sent_requests.retain(|(id, _), _| *id == request_id);
+        let sent = match self.sent_requests.remove(&(request_id, input.to_string()))
+        {
+            Some(sent) => sent,
+            None => return,
+        };
+        let latencies = self.latencies.entry(self.language).or_insert_with(Vec::new);
+        latencies.push(sent.elapsed());
+        if latencies.len() > LATENCY_CAPACITY {
+            latencies.remove(0);
+        }
+    }
+
+    /// The latest completion round-trip latencies for `language`,
+    /// oldest first
+    pub fn latencies(&self, language: Option<LapceLanguage>) -> &[Duration] {
+        self.latencies
+            .get(&language)
+            .map(|latencies| latencies.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The median of the latest round-trip latencies for `language`
+    pub fn typical_latency(
+        &self,
+        language: Option<LapceLanguage>,
+    ) -> Option<Duration> {
+        let latencies = self.latencies(language);
+        if latencies.is_empty() {
+            return None;
+        }
+        let mut sorted = latencies.to_vec();
+        sorted.sort();
+        Some(sorted[sorted.len() / 2])
+    }
+
+    pub fn set_on_accept(&mut self, on_accept: Option<CompletionAcceptCallback>) {
+        self.on_accept = on_accept;
+    }
+
+    /// What accepting the selected item would tell the `on_accept`
+    /// callback, or `None` when there's no item to accept
+    pub fn acceptance(&self) -> Option<CompletionAcceptance> {
+        if self.status == CompletionStatus::Inactive {
+            return None;
+        }
+        let item = self.current_items().get(self.index)?;
+        Some(CompletionAcceptance {
+            label: item.item.label.clone(),
+            kind: item.item.kind,
+            source: item.source.as_deref().map(|source| source.to_string()),
+            rank: self.index,
+            input: self.input.clone(),
+        })
+    }
+
+    /// Tell the `on_accept` callback the selected item is being accepted.
+    /// It's called before the session is cancelled, while the item and the
+    /// input are still around.
+    /// Remember that `item` was accepted in `buffer_id` with the cursor
+    /// ending up at `offset`, so that a member accessor typed right after
+    /// opens completion again, if it's a method or a field and
+    /// `chain_after_accept` is on. It has to come after the `cancel` of
+    /// the session.
+    pub fn accepted_member(
+        &mut self,
+        item: &CompletionItem,
+        buffer_id: BufferId,
+        offset: usize,
+    ) {
+        let is_member = matches!(
+            item.kind,
+            Some(
+                CompletionItemKind::Method
+                    | CompletionItemKind::Field
+                    | CompletionItemKind::Property
+            )
         );
+        self.chained_at = if self.chain_after_accept && is_member {
+            Some((buffer_id, offset))
+        } else {
+            None
+        };
+    }
+
+    /// The member accessor at the end of `prefix`, which ends at `offset`,
+    /// when it was typed right after the member accepted last, see
+    /// `accepted_member`. The chance is used up either way.
+    pub fn chained_trigger(
+        &mut self,
+        buffer_id: BufferId,
+        offset: usize,
+        prefix: &str,
+    ) -> Option<String> {
+        let (chained_buffer, end) = self.chained_at.take()?;
+        if chained_buffer != buffer_id {
+            return None;
+        }
+        ["::", "."]
+            .iter()
+            .find(|c| prefix.ends_with(*c) && end + c.len() == offset)
+            .map(|c| c.to_string())
+    }
+
+    pub fn notify_accepted(&self) {
+        if let Some(on_accept) = self.on_accept.as_ref() {
+            if let Some(acceptance) = self.acceptance() {
+                on_accept(&acceptance);
+            }
+        }
+    }
+
+    pub fn set_on_announce(
+        &mut self,
+        on_announce: Option<CompletionAnnounceCallback>,
+    ) {
+        self.on_announce = on_announce;
+    }
+
+    /// A description of the selected item for screen readers: its label,
+    /// kind and detail, and where it is in the list, like
+    /// `foo, method, fn foo() -> i32, 3 of 40`. The kind and the detail are
+    /// left out when the item doesn't have them.
+    pub fn accessible_label(&self) -> Option<String> {
+        if self.status == CompletionStatus::Inactive {
+            return None;
+        }
+        let item = self.current_items().get(self.index)?;
+        let mut parts = vec![item.label().to_string()];
+        if let Some(kind) = item.item.kind {
+            parts.push(kind_name(kind));
+        }
+        if let Some(detail) = item.item.detail.as_deref().map(single_line) {
+            if !detail.trim().is_empty() {
+                parts.push(detail.trim().to_string());
+            }
+        }
+        parts.push(format!("{} of {}", self.index + 1, self.len()));
+        Some(parts.join(", "))
+    }
+
+    /// Tell the `on_announce` callback about the selected item. The popup
+    /// calls this when the selection changes or the popup shows up.
+    pub fn announce_selection(&self) {
+        if let Some(on_announce) = self.on_announce.as_ref() {
+            if let Some(label) = self.accessible_label() {
+                on_announce(&label);
+            }
+        }
+    }
+
+    /// A readable report of the session for bug reports: what was asked,
+    /// how the items are matched and filtered, and the top ten items with
+    /// what they were ranked by, to tell why one is above another
+    pub fn debug_dump(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "completion {:?}, request {}, offset {}, input {:?}",
+                self.status, self.request_id, self.offset, self.input
+            ),
+            format!(
+                "matching: {:?}, case {:?}, detail {}, min score per char {}, \
+                 boundary bonus {}",
+                self.match_strategy,
+                self.case_matching,
+                self.match_detail,
+                self.min_score_per_char,
+                self.boundary_bonus
+            ),
+            format!(
+                "suppressed kinds: {:?}, kind priority: {:?}",
+                self.suppressed_kinds, self.kind_priority
+            ),
+            format!(
+                "items: {} of {}, {} truncated, {} not loaded, incomplete {}, \
+                 selected {}",
+                self.len(),
+                self.all_items().len(),
+                self.truncated,
+                self.source_capped,
+                self.is_incomplete,
+                self.index
+            ),
+        ];
+        for (i, item) in self.current_items().iter().take(10).enumerate() {
+            lines.push(format!(
+                "{:>3}. {:?} score {} label score {} prefix {} sort text {:?} \
+                 index {} indices {:?}{}",
+                i + 1,
+                item.item.label,
+                item.score,
+                item.label_score,
+                item.prefix_match,
+                item.sort_text,
+                item.index,
+                item.indices,
+                item.source
+                    .as_ref()
+                    .map(|source| format!(" from {source}"))
+                    .unwrap_or_default()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Remember the accepted label, so that it ranks higher next time
+    pub fn record_accepted(&mut self, language: Option<LapceLanguage>, label: &str) {
+        let recent = self.recent.entry(language).or_insert_with(Vec::new);
+        recent.retain(|l| l != label);
+        recent.push(label.to_string());
+        if recent.len() > RECENT_CAPACITY {
+            recent.remove(0);
+        }
+    }
+
+    fn recent_bonus(&self, label: &str) -> i64 {
+        let is_recent = self
+            .recent
+            .get(&self.language)
+            .map(|recent| recent.iter().any(|l| l == label))
+            .unwrap_or(false);
+        if is_recent {
+            RECENT_BONUS
+        } else {
+            0
+        }
+    }
+
+    /// The trigger character of `buffer_id` that `text` ends with, if any.
+    /// Without any registered by the server, `.` and `:` are used.
+    pub fn trigger_character(
+        &self,
+        buffer_id: BufferId,
+        text: &str,
+    ) -> Option<String> {
+        match self.trigger_characters.get(&buffer_id) {
+            Some(characters) => characters
+                .iter()
+                .filter(|c| !c.is_empty() && text.ends_with(c.as_str()))
+                .max_by_key(|c| c.len())
+                .cloned(),
+            None => [".", ":"]
+                .iter()
+                .find(|c| text.ends_with(*c))
+                .map(|c| c.to_string()),
+        }
+    }
+
+    /// Some servers send items with an empty label, which would paint as
+    /// an invisible row. Fall back to the insert text as the label, and
+    /// drop the item if that's empty as well.
+    fn with_label(mut item: CompletionItem) -> Option<CompletionItem> {
+        if item.label.trim().is_empty() {
+            item.label = item
+                .insert_text
+                .clone()
+                .filter(|text| !text.trim().is_empty())?;
+        }
+        Some(item)
+    }
+
+    /// Drop items that render identically, i.e. share the label, kind
+    /// and detail. The items are sorted by `sort_text` already, so the
+    /// first occurrence is the one to keep.
+    fn dedup_items(items: &mut Vec<ScoredCompletionItem>) {
+        let mut seen = HashSet::new();
+        items.retain(|i| {
+            seen.insert((
+                i.item.label.clone(),
+                i.item.kind.map(|k| k as u8),
+                i.item.detail.clone(),
+            ))
+        });
+    }
+
+    /// Select the first item the server marked as `preselect`,
+    /// or the first item if there's none.
+    fn select_preselected(&mut self) {
+        self.index = self
+            .current_items()
+            .iter()
+            .position(|i| i.item.preselect == Some(true))
+            .unwrap_or(0);
+    }
+
+    pub fn filter_items(&mut self) {
+        if self.input.is_empty() {
+            if !self.suppressed_kinds.is_empty() {
+                let items = self
+                    .all_items()
+                    .iter()
+                    .filter(|i| !self.is_suppressed(&i.item))
+                    .cloned()
+                    .collect();
+                self.filtered_items = Arc::new(items);
+            }
+            self.filter_pending = false;
+            self.truncated = 0;
+            self.match_completeness = None;
+            self.update_content_chars();
+            self.clamp_index();
+            return;
+        }
+
+        let items = self.filter(self.all_items());
+        self.set_filtered_items(items);
+    }
+
+    /// Cap the scored items to `max_items`, keeping the best ones
+    fn set_filtered_items(&mut self, mut items: Vec<ScoredCompletionItem>) {
+        self.store_scores(&items);
+        self.filter_pending = false;
+        self.truncated = items.len().saturating_sub(self.max_items);
+        items.truncate(self.max_items);
+        // Only the top item, so that it stays cheap
+        self.match_completeness = items
+            .first()
+            .map(|item| match_completeness(&item.item.label, &self.input));
+        self.filtered_items = Arc::new(items);
+        self.update_content_chars();
+        self.clamp_index();
+    }
+
+    /// Keep what the matcher found for the input on the items of
+    /// `all_items` that `scored` came from, so that filtering with the
+    /// same input again, like when more items come in, doesn't run it
+    fn store_scores(&mut self, scored: &[ScoredCompletionItem]) {
+        let input = self.input.as_str();
+        let scored: HashMap<(usize, &str), &ScoredCompletionItem> = scored
+            .iter()
+            .filter(|i| i.scored_input.as_deref() == Some(input))
+            .map(|i| ((i.index, i.item.label.as_str()), i))
+            .collect();
+        let is_stale = |item: &ScoredCompletionItem| {
+            item.scored_input.as_deref() != Some(input)
+                && scored.contains_key(&(item.index, item.item.label.as_str()))
+        };
+        let key = [input, ""].into_iter().find(|key| {
+            self.input_items
+                .get(*key)
+                .map(|items| !items.is_empty())
+                .unwrap_or(false)
+        });
+        let items = match key {
+            Some(key) => match self.input_items.get_mut(key) {
+                Some(items) => items,
+                None => return,
+            },
+            None => &mut self.buffer_words,
+        };
+        // Not to copy the items when nothing changed
+        if !items.iter().any(is_stale) {
+            return;
+        }
+        for item in Arc::make_mut(items).iter_mut() {
+            if !is_stale(item) {
+                continue;
+            }
+            let i = scored[&(item.index, item.item.label.as_str())];
+            item.match_score = i.match_score;
+            item.boundaries = i.boundaries;
+            item.label_match_score = i.label_match_score;
+            item.indices = i.indices.clone();
+            item.prefix_match = i.prefix_match;
+            item.scored_input = i.scored_input.clone();
+        }
+    }
+
+    /// Keep the selection within the items, for when they change
+    /// under it
+    fn clamp_index(&mut self) {
+        self.index = self.index.min(self.len().saturating_sub(1));
+    }
+
+    fn update_content_chars(&mut self) {
+        let shows_sources = self.shows_sources();
+        self.content_chars = self
+            .current_items()
+            .iter()
+            .map(|i| {
+                let mut label = i.label().chars().count()
+                    + i.label_detail().map(|d| d.chars().count()).unwrap_or(0);
+                if shows_sources {
+                    // The source tag, a character apart from the rest
+                    label += 1 + i
+                        .source
+                        .as_ref()
+                        .map(|s| s.chars().count())
+                        .unwrap_or(0);
+                }
+                match i.detail() {
+                    // The detail is drawn two characters apart from the label
+                    Some(detail) => label + 2 + detail.chars().count(),
+                    None => label,
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        self.compact_labels = !shows_sources
+            && self.current_items().iter().all(|i| {
+                i.detail().is_none()
+                    && i.label_detail().is_none()
+                    && i.label().chars().count() <= COMPACT_LABEL_CHARS
+            });
+    }
+
+    /// Whether the popup leaves out the icon column, which it does when
+    /// `auto_compact` is on and the items shown are like keywords, with
+    /// short labels and nothing else
+    pub fn is_compact(&self) -> bool {
+        self.auto_compact
+            && self.compact_labels
+            && self.display_mode == CompletionDisplayMode::Popup
+    }
+
+    /// The width of the column the kinds of the items are shown in, which
+    /// is square with the rows, or nothing when the popup `is_compact`
+    pub fn icon_width(&self, line_height: f64) -> f64 {
+        if self.is_compact() {
+            0.0
+        } else {
+            line_height
+        }
+    }
+
+    /// The size of the popup fitting the rows, within `MIN_POPUP_WIDTH`,
+    /// `max_size` and `max_visible_rows`
+    pub fn popup_size(&self, char_width: f64, line_height: f64) -> Size {
+        if self.display_mode == CompletionDisplayMode::Inline {
+            let items = &self.current_items()[self.inline_range()];
+            let chars = items
+                .iter()
+                .map(|i| i.label().chars().count())
+                .sum::<usize>()
+                + items.len().saturating_sub(1) * INLINE_SEPARATOR.chars().count();
+            let width = 10.0 + chars as f64 * char_width;
+            let width = width.max(MIN_POPUP_WIDTH).min(self.max_size().width);
+            return Size::new(width, line_height);
+        }
+
+        // The icon column, and the padding around the text
+        let width = self.icon_width(line_height)
+            + 10.0
+            + self.content_chars as f64 * char_width;
+        let min_width = if self.is_compact() {
+            MIN_COMPACT_POPUP_WIDTH
+        } else {
+            MIN_POPUP_WIDTH
+        };
+        let max_size = self.max_size();
+        let width = width.max(min_width).min(max_size.width);
+
+        let mut rows = self.len();
+        if self.more_label().is_some() || self.shows_no_matches() {
+            rows += 1;
+        }
+        if self.max_visible_rows > 0 {
+            rows = rows.min(self.max_visible_rows);
+        }
+        let height = (rows as f64 * line_height).min(max_size.height);
+        Size::new(width, height)
+    }
+
+    fn is_suppressed(&self, item: &CompletionItem) -> bool {
+        item.kind
+            .map(|kind| self.suppressed_kinds.contains(&kind))
+            .unwrap_or(false)
+    }
+
+    /// Hide the items of `kind` if they're shown, show them otherwise
+    pub fn toggle_kind(&mut self, kind: CompletionItemKind) {
+        if let Some(i) = self.suppressed_kinds.iter().position(|k| k == &kind) {
+            self.suppressed_kinds.remove(i);
+        } else {
+            self.suppressed_kinds.push(kind);
+        }
+        self.filter_items();
+        self.select_preselected();
+    }
+
+    /// Only re-filter the items that matched the previous input. This is
+    /// enough when the new input extends the previous one, because an item
+    /// that didn't match a prefix of the input can't match the whole input.
+    /// An item that matched but was dropped by `min_score` might, so this
+    /// isn't used with a minimum.
+    fn narrow_items(&mut self) {
+        let items = self.filtered_items.clone();
+        let items = self.filter(&items);
+        self.set_filtered_items(items);
+    }
+
+    /// The score and the matched character indices of the input in
+    /// `text` with the `match_strategy`
+    fn match_indices(&self, text: &str) -> Option<(i64, Vec<usize>)> {
+        self.match_strategy.match_indices(
+            &self.matcher,
+            self.case_matching,
+            text,
+            &self.input,
+        )
+    }
+
+    /// The score of the input in `text`, without the cost of the indices
+    /// where that's possible
+    fn match_score(&self, text: &str) -> Option<i64> {
+        match self.match_strategy {
+            MatchStrategy::Fuzzy => self.matcher.fuzzy_match(text, &self.input),
+            _ => self.match_indices(text).map(|(score, _)| score),
+        }
+    }
+
+    /// Match `input` in the item, setting everything about the match but
+    /// the bonuses, or `None` when it doesn't match
+    fn score_item(
+        &self,
+        item: &ScoredCompletionItem,
+        input: &Arc<str>,
+        lowercase_input: &str,
+    ) -> Option<ScoredCompletionItem> {
+        let filter_text = item.item.filter_text.as_ref().unwrap_or(&item.item.label);
+        // Where the filter text is found in the label, if it is, so that
+        // the matched positions can be highlighted there
+        let shift = item
+            .item
+            .label
+            .match_indices(filter_text.as_str())
+            .next()
+            .map(|(shift, _)| item.item.label[..shift].chars().count());
+        let text = match item.item.detail.as_ref() {
+            Some(detail) if self.match_detail => {
+                Cow::Owned(format!("{filter_text} {detail}"))
+            }
+            _ => Cow::Borrowed(filter_text.as_str()),
+        };
+        let (score, indices) = self.match_indices(&text)?;
+        // Matches in the detail aren't highlighted, nor do they get the
+        // boundary bonus
+        let text_len = filter_text.chars().count();
+        let indices: Vec<usize> =
+            indices.into_iter().filter(|i| *i < text_len).collect();
+        let boundaries = boundary_matches(filter_text, &indices);
+        let indices: Vec<usize> = match shift {
+            Some(shift) => indices.into_iter().map(|i| i + shift).collect(),
+            None => Vec::new(),
+        };
+        let mut scored = item.clone();
+        scored.match_score = score;
+        scored.boundaries = boundaries;
+        scored.label_match_score = self.match_score(&item.item.label);
+        scored.indices = indices;
+        scored.prefix_match =
+            item.item.label.to_lowercase().starts_with(lowercase_input);
+        scored.scored_input = Some(input.clone());
+        Some(scored)
+    }
+
+    fn filter(&self, items: &[ScoredCompletionItem]) -> Vec<ScoredCompletionItem> {
+        let input: Arc<str> = Arc::from(self.input.as_str());
+        let lowercase_input = self.input.to_lowercase();
+        let min_score = self.min_score();
+        let mut items: Vec<ScoredCompletionItem> = items
+            .iter()
+            .filter_map(|i| {
+                if self.is_suppressed(&i.item) {
+                    return None;
+                }
+                // Only matched items carry a scored input, so a cache hit
+                // means the item still matches with the same match score.
+                // The bonuses, and whether that's enough, may have changed.
+                let mut item = if i.scored_input.as_deref() == Some(&*input) {
+                    i.clone()
+                } else {
+                    self.score_item(i, &input, &lowercase_input)?
+                };
+                if item.match_score < min_score {
+                    return None;
+                }
+                item.score = item.match_score
+                    + self.recent_bonus(&item.item.label)
+                    + self.boundary_bonus * item.boundaries as i64;
+                item.label_score = item.label_match_score.unwrap_or(item.score);
+                Some(item)
+            })
+            .collect();
+        items.sort_by(|a, b| {
+            kind_rank(&self.kind_priority, &a.item)
+                .cmp(&kind_rank(&self.kind_priority, &b.item))
+                .then_with(|| b.prefix_match.cmp(&a.prefix_match))
+                .then_with(|| b.score.cmp(&a.score))
+                .then_with(|| a.sort_text.cmp(&b.sort_text))
+                .then_with(|| b.label_score.cmp(&a.label_score))
+                // The shorter of otherwise equal matches is the more direct
+                // one, like `len` for `length_of_everything`
+                .then_with(|| {
+                    a.item
+                        .label
+                        .chars()
+                        .count()
+                        .cmp(&b.item.label.chars().count())
+                })
+                .then_with(|| a.index.cmp(&b.index))
+        });
+        items
+    }
+}
+
+impl Default for CompletionData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct CompletionNew {}
+
+impl CompletionNew {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for CompletionNew {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse the documentation of a completion item into rich text
+pub fn parse_documentation(
+    documentation: &Documentation,
+    config: &Config,
+) -> RichText {
+    let text = match documentation {
+        Documentation::String(text) => text,
+        Documentation::MarkupContent(content) => match content.kind {
+            MarkupKind::Markdown => {
+                return parse_hover_markdown(
+                    None,
+                    &content.value,
+                    &config.editor,
+                    &config.themes,
+                );
+            }
+            MarkupKind::PlainText => &content.value,
+        },
+    };
+    let mut builder = RichTextBuilder::new();
+    builder.set_line_height(1.5);
+    builder.push(text);
+    builder.build()
+}
+
+#[derive(Clone, Debug)]
+pub struct ScoredCompletionItem {
+    pub item: CompletionItem,
+
+    score: i64,
+    pub label_score: i64,
+    /// The score of the match alone, which `score` adds the bonuses to
+    match_score: i64,
+    /// How many of the matched characters start a word of the filter text,
+    /// see `boundary_matches`
+    boundaries: usize,
+    /// The score of the input in the label, if it matches there
+    label_match_score: Option<i64>,
+    indices: Vec<usize>,
+    /// The server provided `sort_text`, falling back to the label
+    pub sort_text: String,
+    /// The position of the item in the server response
+    index: usize,
+    /// Whether the label starts with the input, ignoring case. Such items
+    /// are sorted ahead of the others, whatever their score.
+    prefix_match: bool,
+    /// The input `match_score`, `boundaries`, `label_match_score` and
+    /// `indices` were computed against
+    pub scored_input: Option<Arc<str>>,
+    /// The name of the language server the item came from
+    pub source: Option<Arc<str>>,
+}
+
+impl ScoredCompletionItem {
+    pub fn new(item: CompletionItem, index: usize) -> Self {
+        Self::from_item(item).with_index(index)
+    }
+
+    /// An unscored item, first in the response, with no source. The rest
+    /// is set with the `with_` methods, e.g. by providers other than
+    /// language servers.
+    pub fn from_item(item: CompletionItem) -> Self {
+        let sort_text = item.sort_text.clone().unwrap_or_else(|| item.label.clone());
+        Self {
+            item,
+            score: 0,
+            label_score: 0,
+            match_score: 0,
+            boundaries: 0,
+            label_match_score: None,
+            indices: Vec::new(),
+            sort_text,
+            index: 0,
+            prefix_match: false,
+            scored_input: None,
+            source: None,
+        }
+    }
+
+    /// Builder-style method for setting the position of the item in the
+    /// response, which orders items that sort the same
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Builder-style method for setting where the item came from
+    pub fn with_source(mut self, source: Option<Arc<str>>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Builder-style method for setting the text the item is sorted by,
+    /// in place of the `sort_text` of the item or its label
+    pub fn with_sort_text(mut self, sort_text: impl Into<String>) -> Self {
+        self.sort_text = sort_text.into();
+        self
+    }
+
+    /// The fuzzy match score against the current input, recency bonus included
+    pub fn score(&self) -> i64 {
+        self.score
+    }
+
+    /// The char indices of the label that matched the current input
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    /// The position of the item in the server response
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The label as painted, on a single line
+    pub fn label(&self) -> Cow<str> {
+        single_line(&self.item.label)
+    }
+
+    /// The `label_details.detail` painted right after the label, like the
+    /// signature of a function, on a single line
+    pub fn label_detail(&self) -> Option<Cow<str>> {
+        self.item
+            .label_details
+            .as_ref()?
+            .detail
+            .as_deref()
+            .map(single_line)
+    }
+
+    /// The detail painted on the right of the row, on a single line. It's
+    /// the `label_details.description`, or the legacy `detail` for servers
+    /// that don't send `label_details`.
+    pub fn detail(&self) -> Option<Cow<str>> {
+        match self.item.label_details.as_ref() {
+            Some(label_details) => {
+                label_details.description.as_deref().map(single_line)
+            }
+            None => self.item.detail.as_deref().map(single_line),
+        }
+    }
+
+    /// The color a `Color` kind item stands for, from the first color in
+    /// its detail or documentation that `parse_color` understands
+    pub fn color(&self) -> Option<Color> {
+        if self.item.kind != Some(CompletionItemKind::Color) {
+            return None;
+        }
+        let documentation =
+            self.item.documentation.as_ref().map(
+                |documentation| match documentation {
+                    Documentation::String(text) => text.as_str(),
+                    Documentation::MarkupContent(content) => content.value.as_str(),
+                },
+            );
+        self.item
+            .detail
+            .as_deref()
+            .into_iter()
+            .chain(documentation)
+            .find_map(parse_color)
+    }
+
+    /// Whether the server marked the item as deprecated, either with the
+    /// legacy `deprecated` flag or the `Deprecated` tag
+    pub fn is_deprecated(&self) -> bool {
+        self.item.deprecated == Some(true)
+            || self
+                .item
+                .tags
+                .as_ref()
+                .map(|tags| tags.contains(&CompletionItemTag::Deprecated))
+                .unwrap_or(false)
+    }
+
+    /// The text to insert when accepting this item, preferring the
+    /// `text_edit`, then the `insert_text` and finally the `label`.
+    ///
+    /// When `snippet` is false, the snippet syntax is stripped so that
+    /// tab stops and placeholders don't end up in the buffer literally.
+    pub fn insert_text(&self, snippet: bool) -> String {
+        let text = item_insert_text(&self.item);
+        if !snippet
+            && self.item.insert_text_format == Some(InsertTextFormat::Snippet)
+        {
+            if let Ok(parsed) = Snippet::from_str(text) {
+                return parsed.text();
+            }
+        }
+        text.to_string()
+    }
+
+    /// The range that should be replaced when accepting this item with the
+    /// cursor at `offset`. A `text_edit` range which doesn't line up with
+    /// the word under the cursor gets extended to cover it, so that the
+    /// typed input is always replaced. The word takes in the `word_chars`
+    /// of the language, see `LapceLanguage::word_chars`.
+    pub fn replace_range(
+        &self,
+        buffer: &Buffer,
+        offset: usize,
+        word_chars: &[char],
+    ) -> (usize, usize) {
+        item_replace_range(&self.item, buffer, offset, word_chars)
+    }
+}
+
+/// The short text shown for `kind` in place of its icon when the
+/// `completion-kind-icons` setting is off, at most three characters so
+/// that it fits the icon column
+pub fn kind_tag(kind: CompletionItemKind) -> &'static str {
+    match kind {
+        CompletionItemKind::Text => "txt",
+        CompletionItemKind::Method => "fn",
+        CompletionItemKind::Function => "fn",
+        CompletionItemKind::Constructor => "new",
+        CompletionItemKind::Field => "fld",
+        CompletionItemKind::Variable => "var",
+        CompletionItemKind::Class => "cls",
+        CompletionItemKind::Interface => "if",
+        CompletionItemKind::Module => "mod",
+        CompletionItemKind::Property => "prp",
+        CompletionItemKind::Unit => "u",
+        CompletionItemKind::Value => "val",
+        CompletionItemKind::Enum => "enm",
+        CompletionItemKind::Keyword => "kw",
+        CompletionItemKind::Snippet => "snp",
+        CompletionItemKind::Color => "clr",
+        CompletionItemKind::File => "fil",
+        CompletionItemKind::Reference => "ref",
+        CompletionItemKind::Folder => "dir",
+        CompletionItemKind::EnumMember => "mbr",
+        CompletionItemKind::Constant => "cst",
+        CompletionItemKind::Struct => "st",
+        CompletionItemKind::Event => "evt",
+        CompletionItemKind::Operator => "op",
+        CompletionItemKind::TypeParameter => "T",
+    }
+}
+
+/// Every `CompletionItemKind`, to look them up by name
+const KINDS: [CompletionItemKind; 25] = [
+    CompletionItemKind::Text,
+    CompletionItemKind::Method,
+    CompletionItemKind::Function,
+    CompletionItemKind::Constructor,
+    CompletionItemKind::Field,
+    CompletionItemKind::Variable,
+    CompletionItemKind::Class,
+    CompletionItemKind::Interface,
+    CompletionItemKind::Module,
+    CompletionItemKind::Property,
+    CompletionItemKind::Unit,
+    CompletionItemKind::Value,
+    CompletionItemKind::Enum,
+    CompletionItemKind::Keyword,
+    CompletionItemKind::Snippet,
+    CompletionItemKind::Color,
+    CompletionItemKind::File,
+    CompletionItemKind::Reference,
+    CompletionItemKind::Folder,
+    CompletionItemKind::EnumMember,
+    CompletionItemKind::Constant,
+    CompletionItemKind::Struct,
+    CompletionItemKind::Event,
+    CompletionItemKind::Operator,
+    CompletionItemKind::TypeParameter,
+];
+
+/// The kinds named in the comma separated `priority`, like
+/// `field, method, enum member`. Case, spaces, dashes and underscores
+/// don't matter, so `EnumMember` and `enum-member` work too. Unknown
+/// names and repeats are skipped.
+pub fn parse_kind_priority(priority: &str) -> Vec<CompletionItemKind> {
+    let key = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    };
+    let mut kinds = Vec::new();
+    for name in priority.split(',') {
+        let name = key(name);
+        let kind = KINDS.iter().find(|kind| key(&kind_name(**kind)) == name);
+        if let Some(kind) = kind {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+    }
+    kinds
+}
+
+/// Where the item's kind is in `kind_priority`, with the kinds that
+/// aren't listed, and items without a kind, all after the listed ones
+fn kind_rank(kind_priority: &[CompletionItemKind], item: &CompletionItem) -> usize {
+    item.kind
+        .and_then(|kind| kind_priority.iter().position(|k| *k == kind))
+        .unwrap_or(kind_priority.len())
+}
+
+/// The name of `kind` as it's read out, like `type parameter` for
+/// `TypeParameter`
+fn kind_name(kind: CompletionItemKind) -> String {
+    let mut name = String::new();
+    for c in format!("{:?}", kind).chars() {
+        if c.is_uppercase() && !name.is_empty() {
+            name.push(' ');
+        }
+        name.extend(c.to_lowercase());
+    }
+    name
+}
+
+/// The identifier-like words around `offset`, nearest first and without
+/// duplicates, for completion in buffers without a language server.
+/// The word `offset` is in is left out, since that's the one being typed.
+pub fn buffer_words(buffer: &Buffer, offset: usize) -> Vec<CompletionItem> {
+    // Whole lines, so that the window doesn't cut a word or a character
+    let start = buffer.offset_of_line(
+        buffer.line_of_offset(offset.saturating_sub(BUFFER_WORDS_WINDOW)),
+    );
+    let end = buffer.offset_of_line(
+        buffer.line_of_offset(offset.saturating_add(BUFFER_WORDS_WINDOW)) + 1,
+    );
+    let text = buffer.slice_to_cow(start..end);
+    words(&text, offset.saturating_sub(start))
+}
+
+fn words(text: &str, offset: usize) -> Vec<CompletionItem> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        let is_word_char = c.is_alphanumeric() || c == '_';
+        match word_start {
+            Some(start) if !is_word_char => {
+                word_start = None;
+                let word = &text[start..i];
+                let is_identifier = word
+                    .chars()
+                    .next()
+                    .map(|c| !c.is_numeric())
+                    .unwrap_or(false);
+                if is_identifier
+                    && word.chars().count() > 1
+                    && !(start..=i).contains(&offset)
+                {
+                    let distance = if i < offset {
+                        offset - i
+                    } else {
+                        start - offset
+                    };
+                    words.push((distance, word));
+                }
+            }
+            None if is_word_char => word_start = Some(i),
+            _ => {}
+        }
+    }
+    words.sort_by_key(|(distance, _)| *distance);
+
+    let mut seen = HashSet::new();
+    words
+        .into_iter()
+        .filter(|(_, word)| seen.insert(*word))
+        .map(|(_, word)| CompletionItem {
+            label: word.to_string(),
+            kind: Some(CompletionItemKind::Text),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The path in the string literal that `line`, the text of a line up to
+/// the cursor, ends inside of, if it looks like one: starting with `/`,
+/// `./`, `../` or `~/`.
+pub fn typed_path(line: &str) -> Option<&str> {
+    let mut quote = None;
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'' | '`') => {
+                quote = Some(c);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    quote?;
+    let path = &line[start..];
+    if ["/", "./", "../", "~/"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// The directory whose entries complete the file name at the end of
+/// `path`, which is everything up to its last `/`. Relative paths are
+/// resolved against `current_dir`, the directory of the file being
+/// edited, and `~` against `home`.
+pub fn path_directory(
+    path: &str,
+    current_dir: Option<&Path>,
+    home: Option<&Path>,
+) -> Option<PathBuf> {
+    let dir = &path[..path.rfind('/')? + 1];
+    if let Some(rest) = dir.strip_prefix("~/") {
+        return Some(home?.join(rest));
+    }
+    if dir.starts_with('/') {
+        return Some(PathBuf::from(dir));
+    }
+    Some(current_dir?.join(dir))
+}
+
+/// Completion items for the entries of a directory, given as their names
+/// and whether they are directories, sorted by name. They replace the
+/// file name from `start` on, which can span more than one word, like
+/// `main.rs`. Accepting a directory also inserts the `/` after it, so that
+/// its entries can be completed next.
+pub fn path_items(
+    entries: impl IntoIterator<Item = (String, bool)>,
+    start: Position,
+) -> Vec<CompletionItem> {
+    entries
+        .into_iter()
+        .sorted()
+        .map(|(name, is_dir)| CompletionItem {
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: lsp_types::Range::new(start, start),
+                new_text: if is_dir {
+                    format!("{}/", name)
+                } else {
+                    name.clone()
+                },
+            })),
+            kind: Some(if is_dir {
+                CompletionItemKind::Folder
+            } else {
+                CompletionItemKind::File
+            }),
+            label: name,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// How many of the char `indices` of `text` start a word: the first
+/// character, an uppercase one after a lowercase one, or one after a
+/// separator like `_`
+fn boundary_matches(text: &str, indices: &[usize]) -> usize {
+    let chars: Vec<char> = text.chars().collect();
+    indices
+        .iter()
+        .filter(
+            |&&i| match (i.checked_sub(1).map(|p| chars[p]), chars.get(i)) {
+                (None, Some(_)) => true,
+                (Some(prev), Some(c)) => {
+                    (c.is_uppercase() && prev.is_lowercase())
+                        || (c.is_alphanumeric() && !prev.is_alphanumeric())
+                }
+                _ => false,
+            },
+        )
+        .count()
+}
+
+/// The share of the characters of `input` found in order in `label`,
+/// regardless of case, taking each as early as it can be. An input that
+/// isn't all found skipped characters to match, like a typo would.
+fn match_completeness(label: &str, input: &str) -> f64 {
+    let label: Vec<char> = label.chars().flat_map(char::to_lowercase).collect();
+    let input: Vec<char> = input.chars().flat_map(char::to_lowercase).collect();
+    if input.is_empty() {
+        return 1.0;
+    }
+    let mut pos = 0;
+    let mut found = 0;
+    for c in &input {
+        if let Some(i) = label[pos..].iter().position(|l| l == c) {
+            found += 1;
+            pos += i + 1;
+        }
+    }
+    found as f64 / input.len() as f64
+}
+
+/// The first `#rgb`, `#rrggbb` or `rgb(r, g, b)` color in `text`, where
+/// `rgba(r, g, b, a)` works too
+fn parse_color(text: &str) -> Option<Color> {
+    for (i, c) in text.char_indices() {
+        if c == '#' {
+            let digits = text[i + 1..]
+                .chars()
+                .take_while(|c| c.is_ascii_hexdigit())
+                .count();
+            if digits == 3 || digits == 6 {
+                if let Ok(color) = Color::from_hex_str(&text[i..i + 1 + digits]) {
+                    return Some(color);
+                }
+            }
+        } else if c == 'r' {
+            let rest = &text[i..];
+            let args = rest
+                .strip_prefix("rgba(")
+                .or_else(|| rest.strip_prefix("rgb("))
+                .and_then(|rest| rest.split(')').next());
+            if let Some(color) = args.and_then(parse_rgb_args) {
+                return Some(color);
+            }
+        }
+    }
+    None
+}
+
+fn parse_rgb_args(args: &str) -> Option<Color> {
+    let args: Vec<&str> = args.split(',').map(|arg| arg.trim()).collect();
+    let (r, g, b, a) = match args.as_slice() {
+        [r, g, b] => (r, g, b, None),
+        [r, g, b, a] => (r, g, b, Some(a.parse::<f64>().ok()?)),
+        _ => return None,
+    };
+    let color = Color::rgb8(r.parse().ok()?, g.parse().ok()?, b.parse().ok()?);
+    Some(match a {
+        Some(a) => color.with_alpha(a.clamp(0.0, 1.0)),
+        None => color,
+    })
+}
+
+/// Merge runs of consecutive indices into ranges, so that a highlighted
+/// match is styled with one attribute per run rather than per character,
+/// e.g. `[0, 1, 2, 5]` into `0..3` and `5..6`
+pub fn coalesce_ranges(indices: &[usize]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for &i in indices {
+        match ranges.last_mut() {
+            Some(range) if range.end == i => range.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+/// Replace line breaks with spaces, so that text some servers send
+/// across lines stays on its row. Char positions are kept, which the
+/// match indices rely on.
+fn single_line(text: &str) -> Cow<str> {
+    if text.contains(&['\n', '\r'][..]) {
+        Cow::Owned(text.replace(&['\n', '\r'][..], " "))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn item_insert_text(item: &CompletionItem) -> &str {
+    match &item.text_edit {
+        Some(CompletionTextEdit::Edit(edit)) => edit.new_text.as_str(),
+        Some(CompletionTextEdit::InsertAndReplace(edit)) => edit.new_text.as_str(),
+        None => item.insert_text.as_deref().unwrap_or(item.label.as_str()),
+    }
+}
+
+fn item_replace_range(
+    item: &CompletionItem,
+    buffer: &Buffer,
+    offset: usize,
+    word_chars: &[char],
+) -> (usize, usize) {
+    let start_offset = buffer.prev_code_boundary_with(offset, word_chars);
+    let end_offset = buffer.next_code_boundary_with(offset, word_chars);
+    match &item.text_edit {
+        Some(CompletionTextEdit::Edit(edit)) => {
+            let edit_start = buffer.offset_of_position(&edit.range.start);
+            let edit_end = buffer.offset_of_position(&edit.range.end);
+            (start_offset.min(edit_start), end_offset.max(edit_end))
+        }
+        Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+            // With the cursor in a word, the whole word is replaced,
+            // rather than the completion inserted in front of its rest
+            let in_word = start_offset < offset || end_offset > offset;
+            let range = if in_word { &edit.replace } else { &edit.insert };
+            (
+                buffer.offset_of_position(&range.start),
+                buffer.offset_of_position(&range.end),
+            )
+        }
+        None => (start_offset, end_offset),
+    }
+}
+
+/// Indent every line of `text` after the first like the line `offset` is
+/// on, for items with the `AdjustIndentation` insert text mode. Tabs the
+/// lines start with are turned into the indent unit of the buffer.
+fn adjust_indentation<'a>(
+    text: &'a str,
+    buffer: &Buffer,
+    offset: usize,
+) -> Cow<'a, str> {
+    if !text.contains('\n') {
+        return Cow::Borrowed(text);
+    }
+    let indent = buffer.indent_on_line(buffer.line_of_offset(offset));
+    let indent_unit = buffer.indent_unit();
+    let mut lines = text.split('\n');
+    let mut adjusted = lines.next().unwrap_or("").to_string();
+    for line in lines {
+        adjusted.push('\n');
+        let content = line.trim_start_matches('\t');
+        if !content.is_empty() {
+            adjusted.push_str(&indent);
+        }
+        for _ in 0..line.len() - content.len() {
+            adjusted.push_str(indent_unit);
+        }
+        adjusted.push_str(content);
+    }
+    Cow::Owned(adjusted)
+}
+
+/// The edits accepting a completion item makes to the buffer
+#[derive(Debug)]
+pub struct CompletionEdit {
+    /// The start of the range the primary edit replaces
+    pub start: usize,
+    /// The end of the range the primary edit replaces
+    pub end: usize,
+    /// The text of the primary edit, without any snippet syntax
+    pub text: String,
+    /// The parsed snippet, if the item is one, for its tab stops
+    pub snippet: Option<Snippet>,
+    /// The `additional_text_edits`, such as an import of the completed
+    /// symbol, as `(start, end, text)`. They're in reverse document order
+    /// so that none of them shifts the range of another.
+    pub additional_edits: Vec<(usize, usize, String)>,
+}
+
+impl CompletionEdit {
+    /// The edit of the item with the cursor at `offset`, replacing the word
+    /// there, which takes in the `word_chars` of the language
+    pub fn new(
+        item: &CompletionItem,
+        buffer: &Buffer,
+        offset: usize,
+        word_chars: &[char],
+    ) -> Self {
+        let (start, end) = item_replace_range(item, buffer, offset, word_chars);
+        let mut edit = Self::replacing(item, buffer, start, end);
+        edit.additional_edits = item
+            .additional_text_edits
+            .iter()
+            .flatten()
+            .map(|edit| {
+                (
+                    buffer.offset_of_position(&edit.range.start),
+                    buffer.offset_of_position(&edit.range.end),
+                    edit.new_text.clone(),
+                )
+            })
+            .collect();
+        edit.additional_edits
+            .sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+        edit
+    }
+
+    /// The edits of the item at each of `offsets`, for multiple cursors.
+    /// The first offset is the cursor the completion was started at, which
+    /// gets the edit of `new`. The others replace the prefix of the word
+    /// before their own cursor, whatever it is, and leave the additional
+    /// edits to the first. They replace the whole word only when the first
+    /// does too, for an `InsertReplaceEdit` in replace mode. A cursor in
+    /// the word of an earlier one is left out, as their edits would overlap.
+    pub fn at_cursors(
+        item: &CompletionItem,
+        buffer: &Buffer,
+        offsets: &[usize],
+        word_chars: &[char],
+    ) -> Vec<Self> {
+        let (first, rest) = match offsets.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+        let edit = Self::new(item, buffer, *first, word_chars);
+        let replace_word = matches!(
+            item.text_edit,
+            Some(CompletionTextEdit::InsertAndReplace(_))
+        ) && edit.end > *first;
+        let mut edits = vec![edit];
+        for offset in rest {
+            let start = buffer.prev_code_boundary_with(*offset, word_chars);
+            let end = if replace_word {
+                buffer.next_code_boundary_with(*offset, word_chars)
+            } else {
+                *offset
+            };
+            if edits
+                .iter()
+                .all(|edit| end < edit.start || start > edit.end)
+            {
+                edits.push(Self::replacing(item, buffer, start, end));
+            }
+        }
+        edits
+    }
+
+    /// The primary edit of the item, replacing `start..end`
+    fn replacing(
+        item: &CompletionItem,
+        buffer: &Buffer,
+        start: usize,
+        end: usize,
+    ) -> Self {
+        let text = item_insert_text(item);
+        // Indenting the raw text keeps the snippet tab stops in step
+        let text =
+            if item.insert_text_mode == Some(InsertTextMode::AdjustIndentation) {
+                adjust_indentation(text, buffer, start)
+            } else {
+                Cow::Borrowed(text)
+            };
+        let snippet = if item.insert_text_format == Some(InsertTextFormat::Snippet) {
+            Snippet::from_str(&text).ok()
+        } else {
+            None
+        };
+        let text = snippet
+            .as_ref()
+            .map(|snippet| snippet.text())
+            .unwrap_or_else(|| text.to_string());
+
+        Self {
+            start,
+            end,
+            text,
+            snippet,
+            additional_edits: Vec::new(),
+        }
+    }
+
+    /// Append `()` to the completion of a function or method, as a snippet
+    /// with the cursor between them. Snippets are left alone since they
+    /// bring their own arguments, and so are calls already followed by a
+    /// `(`, which would double up.
+    pub fn with_parentheses(
+        mut self,
+        item: &CompletionItem,
+        buffer: &Buffer,
+    ) -> Self {
+        let callable = matches!(
+            item.kind,
+            Some(CompletionItemKind::Function | CompletionItemKind::Method)
+        );
+        if !callable
+            || self.snippet.is_some()
+            || buffer.char_at_offset(self.end) == Some('(')
+        {
+            return self;
+        }
+        self.snippet = Some(Snippet {
+            elements: vec![
+                SnippetElement::Text(format!("{}(", self.text)),
+                SnippetElement::Tabstop(0),
+                SnippetElement::Text(")".to_string()),
+            ],
+        });
+        self.text.push_str("()");
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{
+        CompletionItemLabelDetails, CompletionList, InsertReplaceEdit, Range,
+    };
+
+    #[test]
+    fn test_snippet() {
+        let s = "start $1${2:second ${3:third}} $0";
+        let parsed = Snippet::from_str(s).unwrap();
+        assert_eq!(s, parsed.to_string());
+
+        let text = "start second third ";
+        assert_eq!(text, parsed.text());
+
+        assert_eq!(
+            vec![(1, (6, 6)), (2, (6, 18)), (3, (13, 18)), (0, (19, 19))],
+            parsed.tabs(0)
+        );
+    }
+
+    #[test]
+    fn test_snippet_tabs() {
+        let parsed = Snippet::from_str("${2:b}, ${1:a}$0").unwrap();
+        assert_eq!("b, a", parsed.text());
+        assert_eq!(
+            vec![(1, (13, 14)), (2, (10, 11)), (0, (14, 14))],
+            parsed.tabs(10)
+        );
+
+        let parsed = Snippet::from_str("foo(${1:x${2:y}})").unwrap();
+        assert_eq!("foo(xy)", parsed.text());
+        assert_eq!(vec![(1, (4, 6)), (2, (5, 6)), (0, (7, 7))], parsed.tabs(0));
+    }
+
+    fn item(label: &str) -> CompletionItem {
+        CompletionItem {
+            label: label.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The labels of the items shown, in their order
+    fn labels(completion: &CompletionData) -> Vec<String> {
+        completion
+            .current_items()
+            .iter()
+            .map(|i| i.item.label.clone())
+            .collect()
+    }
+
+    /// The labels of the items shown, sorted, for when the order isn't
+    /// what's tested
+    fn sorted_labels(completion: &CompletionData) -> Vec<String> {
+        labels(completion).into_iter().sorted().collect()
+    }
+
+    fn completion_with_items(labels: &[&str]) -> CompletionData {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = labels.iter().map(|label| item(label)).collect();
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion
+    }
+
+    #[test]
+    fn test_page_movement() {
+        let labels: Vec<String> = (0..50).map(|i| format!("item{:02}", i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let mut completion = completion_with_items(&labels);
+        completion.size = Size::new(400.0, 250.0);
+
+        completion.page_next(25.0);
+        assert_eq!(10, completion.index);
+        completion.page_next(25.0);
+        assert_eq!(20, completion.index);
+        completion.page_previous(25.0);
+        assert_eq!(10, completion.index);
+        completion.page_previous(25.0);
+        completion.page_previous(25.0);
+        assert_eq!(0, completion.index);
+
+        completion.index = 45;
+        completion.page_next(25.0);
+        assert_eq!(49, completion.index);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut completion = completion_with_items(&["a", "b", "c"]);
+
+        completion.previous();
+        assert_eq!(completion.index, 2);
+        completion.next();
+        assert_eq!(completion.index, 0);
+
+        completion.set_wrap(false);
+        completion.previous();
+        assert_eq!(completion.index, 0);
+        completion.index = 2;
+        completion.next();
+        assert_eq!(completion.index, 2);
+    }
+
+    #[test]
+    fn test_navigation_boundaries() {
+        for wrap in [true, false] {
+            let mut completion = completion_with_items(&["a", "b", "c"]);
+            completion.set_wrap(wrap);
+
+            completion.previous();
+            assert_eq!(completion.index, if wrap { 2 } else { 0 });
+            completion.last();
+            completion.next();
+            assert_eq!(completion.index, if wrap { 0 } else { 2 });
+
+            let mut completion = completion_with_items(&[]);
+            completion.set_wrap(wrap);
+            completion.next();
+            assert_eq!(completion.index, 0);
+            completion.previous();
+            assert_eq!(completion.index, 0);
+            assert!(completion.acceptance().is_none());
+        }
+    }
+
+    #[test]
+    fn test_first_last() {
+        let mut completion = completion_with_items(&["a", "b", "c"]);
+        completion.last();
+        assert_eq!(completion.index, 2);
+        completion.first();
+        assert_eq!(completion.index, 0);
+
+        let mut completion = completion_with_items(&[]);
+        completion.last();
+        assert_eq!(completion.index, 0);
+        completion.first();
+        assert_eq!(completion.index, 0);
+    }
+
+    #[test]
+    fn test_empty_label() {
+        let completion = completion_with_items(&["", "foo", " "]);
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current_item().item.label, "foo");
+
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let item = CompletionItem {
+            label: "".to_string(),
+            insert_text: Some("bar".to_string()),
+            ..Default::default()
+        };
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(vec![item]),
+            None,
+        );
+        assert_eq!(completion.current_item().item.label, "bar");
+    }
+
+    #[test]
+    fn test_single_line_label() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let item = CompletionItem {
+            label: "foo\nbar".to_string(),
+            detail: Some("fn()\r\n-> u32".to_string()),
+            ..Default::default()
+        };
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(vec![item]),
+            None,
+        );
+
+        let item = completion.current_item();
+        assert_eq!(item.label(), "foo bar");
+        assert_eq!(item.detail().unwrap(), "fn()  -> u32");
+        assert_eq!(completion.content_chars, 7 + 2 + 12);
+    }
+
+    #[test]
+    fn test_receive_chunk() {
+        let items = |labels: &[&str]| {
+            labels.iter().map(|label| item(label)).collect::<Vec<_>>()
+        };
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.request_id = 1;
+        completion.latest_request_id = 1;
+
+        completion.receive_chunk(
+            1,
+            "".to_string(),
+            items(&["foo", "bar"]),
+            None,
+            false,
+        );
+        assert!(completion.is_streaming());
+        assert_eq!(labels(&completion), vec!["bar", "foo"]);
+        completion.next();
+        assert_eq!(completion.current_item().item.label, "foo");
+
+        // The `foo` already received isn't added again, and it stays
+        // selected as the items around it come in
+        completion.receive_chunk(
+            1,
+            "".to_string(),
+            items(&["foo", "baz", "abc"]),
+            None,
+            false,
+        );
+        assert_eq!(labels(&completion), vec!["abc", "bar", "baz", "foo"]);
+        assert_eq!(completion.current_item().item.label, "foo");
+
+        completion.update_input("ba".to_string());
+        completion.receive_chunk(
+            1,
+            "".to_string(),
+            items(&["bax", "bar"]),
+            None,
+            true,
+        );
+        assert!(!completion.is_streaming());
+        assert_eq!(labels(&completion), vec!["bar", "bax", "baz"]);
+
+        // A new request starts over, and the chunks still coming for the
+        // one it superseded are dropped
+        completion.request_id = 2;
+        completion.latest_request_id = 2;
+        completion.receive_chunk(2, "".to_string(), items(&["bat"]), None, false);
+        assert_eq!(labels(&completion), vec!["bat"]);
+        completion.receive_chunk(1, "".to_string(), items(&["bay"]), None, true);
+        assert_eq!(labels(&completion), vec!["bat"]);
+        assert!(completion.is_streaming());
+
+        completion.cancel();
+        assert!(!completion.is_streaming());
+    }
+
+    #[test]
+    fn test_receive_after_cancel() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.request_id = 1;
+        completion.latest_request_id = 1;
+        completion.cancel();
+
+        completion.receive(
+            1,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("foo")]),
+            None,
+        );
+        assert!(completion.status == CompletionStatus::Inactive);
+        assert!(completion.input_items.is_empty());
+        assert!(!completion.is_visible());
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.request_id = 2;
+        completion.latest_request_id = 2;
+        completion
+            .sent_requests
+            .insert((1, "".to_string()), Instant::now());
+        completion
+            .sent_requests
+            .insert((2, "".to_string()), Instant::now());
+
+        // A stale response doesn't record anything
+        completion.receive(
+            1,
+            "".to_string(),
+            CompletionResponse::Array(vec![]),
+            None,
+        );
+        assert!(completion.latencies(None).is_empty());
+
+        completion.receive(
+            2,
+            "".to_string(),
+            CompletionResponse::Array(vec![]),
+            None,
+        );
+        assert_eq!(completion.latencies(None).len(), 1);
+        assert!(completion.sent_requests.is_empty());
+        assert!(completion.typical_latency(None).is_some());
+
+        // A response that wasn't waited for doesn't either
+        completion.receive(
+            2,
+            "".to_string(),
+            CompletionResponse::Array(vec![]),
+            None,
+        );
+        assert_eq!(completion.latencies(None).len(), 1);
+    }
+
+    #[test]
+    fn test_is_active_and_nonempty() {
+        let mut completion = completion_with_items(&["foo"]);
+        assert!(completion.is_active_and_nonempty());
+
+        completion.update_input("xyz".to_string());
+        assert!(!completion.is_active_and_nonempty());
+
+        completion.update_input("f".to_string());
+        completion.cancel();
+        assert!(!completion.is_active_and_nonempty());
+    }
+
+    #[test]
+    fn test_inline_display_mode() {
+        let labels: Vec<String> = (0..20).map(|i| format!("item{:02}", i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let mut completion = completion_with_items(&labels);
+        completion.set_display_mode(CompletionDisplayMode::Inline);
+        assert_eq!(completion.inline_range(), 0..8);
+
+        completion.index = 9;
+        assert_eq!(completion.inline_range(), 8..16);
+        completion.last();
+        assert_eq!(completion.inline_range(), 16..20);
+
+        // One row, fitting the four labels and the separators between them
+        let size = completion.popup_size(10.0, 20.0);
+        assert_eq!(size, Size::new(10.0 + (4 * 6 + 3 * 3) as f64 * 10.0, 20.0));
+    }
+
+    #[test]
+    fn test_clamp_index() {
+        let mut completion =
+            completion_with_items(&["foo", "bar", "baz", "qux", "quux"]);
+        completion.update_input("ba".to_string());
+        completion.index = 1;
+
+        completion.max_items = 1;
+        completion.filter_items();
+        assert_eq!(completion.index, 0);
+        assert_eq!(completion.current(), completion.current_item().item.label);
+
+        completion.index = 3;
+        completion.toggle_kind(CompletionItemKind::Keyword);
+        assert_eq!(completion.index, 0);
+
+        completion.update_input("xyz".to_string());
+        assert_eq!(completion.current(), "");
+    }
+
+    #[test]
+    fn test_coalesce_ranges() {
+        assert!(coalesce_ranges(&[]).is_empty());
+        assert_eq!(coalesce_ranges(&[3]), vec![3..4]);
+        assert_eq!(coalesce_ranges(&[0, 1, 2, 5]), vec![0..3, 5..6]);
+        assert_eq!(coalesce_ranges(&[1, 3, 4, 6, 7, 8]), vec![1..2, 3..5, 6..9]);
+    }
+
+    #[test]
+    fn test_is_visible() {
+        let mut completion = CompletionData::new();
+        assert!(!completion.is_visible());
+
+        // Started, with the response still in flight
+        completion.status = CompletionStatus::Started;
+        assert!(!completion.is_visible());
+
+        let mut completion = completion_with_items(&["foo"]);
+        assert!(completion.is_visible());
+
+        completion.update_input("xyz".to_string());
+        assert!(!completion.is_visible());
+
+        completion.update_input("f".to_string());
+        assert!(completion.is_visible());
+        completion.cancel();
+        assert!(!completion.is_visible());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#fff"), Some(Color::rgb8(255, 255, 255)));
+        assert_eq!(
+            parse_color("color: #00ff80;"),
+            Some(Color::rgb8(0, 255, 128))
+        );
+        assert_eq!(parse_color("rgb(1, 2, 3)"), Some(Color::rgb8(1, 2, 3)));
+        assert_eq!(
+            parse_color("rgba(1,2,3,0.5)"),
+            Some(Color::rgb8(1, 2, 3).with_alpha(0.5))
+        );
+        assert_eq!(parse_color("#ffff"), None);
+        assert_eq!(parse_color("rgb(300, 0, 0)"), None);
+        assert_eq!(parse_color("red"), None);
+    }
+
+    #[test]
+    fn test_color() {
+        let item = |kind, detail: &str| {
+            ScoredCompletionItem::new(
+                CompletionItem {
+                    label: "red".to_string(),
+                    kind: Some(kind),
+                    detail: Some(detail.to_string()),
+                    ..Default::default()
+                },
+                0,
+            )
+        };
+        assert_eq!(
+            item(CompletionItemKind::Color, "#ff0000").color(),
+            Some(Color::rgb8(255, 0, 0))
+        );
+        assert_eq!(item(CompletionItemKind::Color, "red").color(), None);
+        assert_eq!(item(CompletionItemKind::Text, "#ff0000").color(), None);
+
+        let documented = ScoredCompletionItem::new(
+            CompletionItem {
+                label: "red".to_string(),
+                kind: Some(CompletionItemKind::Color),
+                documentation: Some(Documentation::String(
+                    "rgb(255, 0, 0)".to_string(),
+                )),
+                ..Default::default()
+            },
+            0,
+        );
+        assert_eq!(documented.color(), Some(Color::rgb8(255, 0, 0)));
+    }
+
+    #[test]
+    fn test_selection_span() {
+        let labels: Vec<String> = (0..20).map(|i| format!("item{:02}", i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let mut completion = completion_with_items(&labels);
+        completion.select(10);
+        assert_eq!(completion.selection_span(10.0, 50.0), (100.0, 110.0));
+
+        completion.center_selection = true;
+        assert_eq!(completion.selection_span(10.0, 50.0), (80.0, 130.0));
+
+        // Clamped at the ends of the list
+        completion.select(1);
+        assert_eq!(completion.selection_span(10.0, 50.0), (0.0, 50.0));
+        completion.select(19);
+        assert_eq!(completion.selection_span(10.0, 50.0), (150.0, 200.0));
+    }
+
+    #[test]
+    fn test_on_accept() {
+        let accepted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut completion = completion_with_items(&["foo", "bar", "baz"]);
+        let on_accept = accepted.clone();
+        completion.set_on_accept(Some(Arc::new(move |acceptance| {
+            on_accept.lock().unwrap().push(acceptance.clone());
+        })));
+        completion.update_input("ba".to_string());
+        completion.next();
+
+        completion.notify_accepted();
+        completion.cancel();
+        completion.notify_accepted();
+        assert_eq!(
+            *accepted.lock().unwrap(),
+            vec![CompletionAcceptance {
+                label: "baz".to_string(),
+                kind: None,
+                source: None,
+                rank: 1,
+                input: "ba".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_filter_in_background() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.background_filter_min = Some(3);
+        completion.input = "ba".to_string();
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("foo"), item("bar"), item("baz")]),
+            None,
+        );
+        assert!(completion.is_filter_pending());
+        assert!(completion.is_empty());
+
+        // What the background thread sends back
+        let items = completion.filter(completion.all_items());
+        completion.receive_filtered(0, "b", items.clone());
+        assert!(completion.is_empty());
+        completion.receive_filtered(0, "ba", items.clone());
+        assert!(!completion.is_filter_pending());
+        assert_eq!(completion.len(), 2);
+
+        // Typing before the result arrives filters the items right away
+        completion.filtered_items = Arc::new(Vec::new());
+        completion.filter_pending = true;
+        completion.update_input("baz".to_string());
+        assert!(!completion.is_filter_pending());
+        assert_eq!(completion.current(), "baz");
+        completion.receive_filtered(0, "ba", items);
+        assert_eq!(completion.len(), 1);
+    }
+
+    #[test]
+    fn test_position() {
+        let mut completion = completion_with_items(&["foo", "bar", "baz"]);
+        assert_eq!(completion.position().unwrap(), "1/3");
+        completion.next();
+        assert_eq!(completion.position().unwrap(), "2/3");
+
+        completion.update_input("baz".to_string());
+        assert_eq!(completion.position(), None);
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let mut config = Config::default();
+        config.editor.completion_match_highlight = false;
+        config.editor.completion_auto_compact = true;
+        let mut completion = completion_with_items(&["foo", "bar", "baz"]);
+        completion.update_input("ba".to_string());
+        let size = completion.popup_size(8.0, 20.0);
+
+        completion.update_config(&config);
+        assert!(!completion.highlight_matches);
+        // Only the painting changes, not the size of the popup
+        assert_eq!(completion.popup_size(8.0, 20.0), size);
+        assert_eq!(completion.current_indices(), &[0, 1]);
+    }
+
+    #[test]
+    fn test_should_repaint() {
+        let completion = completion_with_items(&["foo", "bar", "baz"]);
+        assert!(!completion.should_repaint(&completion.clone()));
+
+        let mut moved = completion.clone();
+        moved.next();
+        assert!(moved.should_repaint(&completion));
+
+        let mut filtered = completion.clone();
+        filtered.update_input("ba".to_string());
+        assert!(filtered.should_repaint(&completion));
+
+        let mut unhighlighted = completion.clone();
+        unhighlighted.highlight_matches = false;
+        assert!(unhighlighted.should_repaint(&completion));
+
+        // Not something the popup shows
+        let mut wrapping = completion.clone();
+        wrapping.set_wrap(false);
+        assert!(!wrapping.should_repaint(&completion));
+    }
+
+    #[test]
+    fn test_toggle_documentation() {
+        let mut completion = completion_with_items(&["foo", "bar", "baz"]);
+        completion.next();
+        completion.toggle_documentation();
+        assert_eq!(
+            completion.documentation_box_size(),
+            completion.expanded_documentation_size
+        );
+        // Still showing the same selection
+        assert!(completion.is_visible());
+        assert_eq!(completion.current(), "bar");
+
+        completion.toggle_documentation();
+        assert_eq!(
+            completion.documentation_box_size(),
+            completion.documentation_size
+        );
+
+        completion.toggle_documentation();
+        completion.cancel();
+        assert!(!completion.documentation_expanded);
+    }
+
+    #[test]
+    fn test_no_matches() {
+        let mut completion = completion_with_items(&["foo"]);
+        completion.show_no_matches = true;
+        assert!(!completion.shows_no_matches());
+
+        completion.update_input("xyz".to_string());
+        assert!(completion.shows_no_matches());
+        assert!(completion.is_visible());
+        assert!(!completion.is_active_and_nonempty());
+        assert_eq!(completion.row_at(5.0, 20.0), None);
+        assert_eq!(completion.popup_size(8.0, 20.0).height, 20.0);
+
+        completion.show_no_matches = false;
+        assert!(!completion.is_visible());
+    }
+
+    #[test]
+    fn test_label_details() {
+        let item = ScoredCompletionItem::new(
+            CompletionItem {
+                label: "foo".to_string(),
+                detail: Some("fn foo(a: u32) -> u32".to_string()),
+                label_details: Some(CompletionItemLabelDetails {
+                    detail: Some("(a: u32)".to_string()),
+                    description: Some("u32".to_string()),
+                }),
+                ..Default::default()
+            },
+            0,
+        );
+        assert_eq!(item.label_detail().unwrap(), "(a: u32)");
+        assert_eq!(item.detail().unwrap(), "u32");
+
+        let item = ScoredCompletionItem::new(
+            CompletionItem {
+                label: "foo".to_string(),
+                detail: Some("fn foo(a: u32) -> u32".to_string()),
+                ..Default::default()
+            },
+            0,
+        );
+        assert!(item.label_detail().is_none());
+        assert_eq!(item.detail().unwrap(), "fn foo(a: u32) -> u32");
+    }
+
+    #[test]
+    fn test_is_at() {
+        let mut completion = completion_with_items(&["foo"]);
+        completion.buffer_id = BufferId(1);
+        completion.offset = 10;
+        completion.update_input("fo".to_string());
+
+        assert!(completion.is_at(BufferId(1), 10));
+        assert!(completion.is_at(BufferId(1), 12));
+        assert!(!completion.is_at(BufferId(1), 9));
+        assert!(!completion.is_at(BufferId(1), 13));
+        assert!(!completion.is_at(BufferId(2), 10));
+    }
+
+    #[test]
+    fn test_delete_boundaries() {
+        // Completing after `foo.`, with `ba` typed
+        let mut completion = completion_with_items(&["bar", "baz", "foo"]);
+        completion.buffer_id = BufferId(1);
+        completion.offset = 4;
+        completion.update_input("ba".to_string());
+        assert_eq!(completion.len(), 2);
+
+        // Deleting within the input keeps filtering
+        completion.update_input("b".to_string());
+        assert!(completion.is_at(BufferId(1), 5));
+        assert!(!completion.is_behind(BufferId(1), 5));
+        assert_eq!(completion.len(), 2);
+
+        // Deleting the whole input leaves the cursor right after the
+        // trigger character, which lists everything again
+        assert!(!completion.is_behind(BufferId(1), 4));
+        assert_eq!(
+            completion.trigger_character(BufferId(1), "o."),
+            Some(".".to_string())
+        );
+        completion.update_input("".to_string());
+        assert_eq!(completion.len(), 3);
+
+        // Deleting the trigger character too ends the session
+        assert!(completion.is_behind(BufferId(1), 3));
+        assert!(!completion.is_behind(BufferId(2), 3));
+        completion.cancel();
+        assert!(!completion.is_behind(BufferId(1), 3));
+    }
+
+    #[test]
+    fn test_rapid_sessions() {
+        let start = |completion: &mut CompletionData| {
+            let request_id = completion.request_id;
+            completion.status = CompletionStatus::Started;
+            completion.receive(
+                request_id,
+                "".to_string(),
+                CompletionResponse::Array(vec![item("foo"), item("bar")]),
+                None,
+            );
+            completion.update_input("f".to_string());
+        };
+
+        // Sessions opened and cancelled as fast as they're typed, each
+        // leaving a response, a resolve, a background filter and a merge
+        // in flight.
+        // They're started without a new request id, so that only cancel
+        // tells them apart.
+        let mut completion = CompletionData::new();
+        let mut superseded = Vec::new();
+        for _ in 0..1000 {
+            start(&mut completion);
+            superseded.push(completion.request_id);
+            completion.cancel();
+            // Cancelling what's already cancelled changes nothing
+            let request_id = completion.request_id;
+            completion.cancel();
+            assert_eq!(completion.request_id, request_id);
+        }
+        superseded.dedup();
+        assert_eq!(superseded.len(), 1000);
+
+        start(&mut completion);
+        completion.filter_pending = true;
+        for request_id in superseded {
+            completion.receive_resolve(
+                request_id,
+                0,
+                CompletionItem {
+                    documentation: Some(Documentation::String("stale".to_string())),
+                    ..item("foo")
+                },
+            );
+            completion.receive(
+                request_id,
+                "f".to_string(),
+                CompletionResponse::Array(vec![item("fizz")]),
+                None,
+            );
+            completion.receive_filtered(request_id, "f", Vec::new());
+            completion.merge_items(request_id, vec![item("fizz")], None);
+        }
+        assert!(completion.is_filter_pending());
+        completion.filter_pending = false;
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current(), "foo");
+        assert!(completion.current_item().item.documentation.is_none());
+        assert_eq!(completion.all_items().len(), 2);
+
+        // While what's for the new session still comes through
+        let request_id = completion.request_id;
+        completion.merge_items(request_id, vec![item("fizz")], None);
+        assert_eq!(completion.len(), 2);
+    }
+
+    #[test]
+    fn test_auto_accept_exact() {
+        let mut completion = completion_with_items(&["Foo", "foobar", "bar"]);
+
+        // Off by default, even for a sole exact match
+        completion.update_input("bar".to_string());
+        assert_eq!(completion.len(), 1);
+        assert!(!completion.auto_accept_pending());
+
+        completion.auto_accept_exact = true;
+        completion.update_input("bar".to_string());
+        assert!(completion.auto_accept_pending());
+
+        // The only match, but not all of it typed yet
+        completion.update_input("ba".to_string());
+        assert_eq!(completion.len(), 1);
+        assert!(!completion.auto_accept_pending());
+
+        // Exactly one of the items, but a longer one matches as well
+        completion.update_input("foo".to_string());
+        assert_eq!(completion.len(), 2);
+        assert!(!completion.auto_accept_pending());
+
+        // A longer match that could still turn up from the server
+        completion.update_input("fooba".to_string());
+        completion.is_incomplete = true;
+        completion.update_input("foobar".to_string());
+        assert_eq!(completion.len(), 1);
+        assert!(!completion.auto_accept_pending());
+        completion.is_incomplete = false;
+
+        // Case doesn't matter for the match
+        completion.update_input("FOOBAR".to_string());
+        assert!(completion.auto_accept_pending());
+
+        completion.cancel();
+        assert!(!completion.auto_accept_pending());
+    }
+
+    #[test]
+    fn test_chain_after_accept() {
+        let mut completion = CompletionData::new();
+        let buffer_id = BufferId(1);
+        let method = CompletionItem {
+            label: "bar".to_string(),
+            kind: Some(CompletionItemKind::Method),
+            ..Default::default()
+        };
+        let keyword = CompletionItem {
+            label: "let".to_string(),
+            kind: Some(CompletionItemKind::Keyword),
+            ..Default::default()
+        };
+
+        // Off by default
+        completion.accepted_member(&method, buffer_id, 7);
+        assert_eq!(completion.chained_trigger(buffer_id, 8, "r."), None);
+
+        completion.chain_after_accept = true;
+        completion.accepted_member(&method, buffer_id, 7);
+        assert_eq!(
+            completion.chained_trigger(buffer_id, 8, "r."),
+            Some(".".to_string())
+        );
+        // Used up by the first character typed
+        assert_eq!(completion.chained_trigger(buffer_id, 8, "r."), None);
+
+        completion.accepted_member(&method, buffer_id, 7);
+        assert_eq!(
+            completion.chained_trigger(buffer_id, 9, "::"),
+            Some("::".to_string())
+        );
+
+        // Not right after the accepted member
+        completion.accepted_member(&method, buffer_id, 7);
+        assert_eq!(completion.chained_trigger(buffer_id, 9, " ."), None);
+
+        // In another buffer
+        completion.accepted_member(&method, buffer_id, 7);
+        assert_eq!(completion.chained_trigger(BufferId(2), 8, "r."), None);
+
+        // Only methods and fields are chained on
+        completion.accepted_member(&keyword, buffer_id, 7);
+        assert_eq!(completion.chained_trigger(buffer_id, 8, "t."), None);
+
+        // Moving away cancels, which ends the chance as well
+        completion.accepted_member(&method, buffer_id, 7);
+        completion.cancel();
+        assert_eq!(completion.chained_trigger(buffer_id, 8, "r."), None);
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("foo")]),
+            Some("rust-analyzer".to_string()),
+        );
+        assert_eq!(
+            completion.current_item().source.as_deref(),
+            Some("rust-analyzer")
+        );
+        // A single server isn't worth tagging
+        assert!(!completion.shows_sources());
+
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.input_items.insert(
+            "".to_string(),
+            Arc::new(vec![
+                ScoredCompletionItem::new(item("foo"), 0)
+                    .with_source(Some(Arc::from("a"))),
+                ScoredCompletionItem::new(item("bar"), 1)
+                    .with_source(Some(Arc::from("b"))),
+            ]),
+        );
+        assert!(completion.shows_sources());
+        completion.show_source = false;
+        assert!(!completion.shows_sources());
+    }
+
+    #[test]
+    fn test_shorter_label_first() {
+        let items = ["length_of_everything", "len_b", "len_a", "len"]
+            .iter()
+            .map(|label| CompletionItem {
+                label: label.to_string(),
+                sort_text: Some("a".to_string()),
+                ..Default::default()
+            })
+            .collect();
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion.update_input("len".to_string());
+        let labels: Vec<&str> = completion
+            .current_items()
+            .iter()
+            .map(|i| i.item.label.as_str())
+            .collect();
+        // Labels of the same length keep the server order
+        assert_eq!(labels, ["len", "len_b", "len_a", "length_of_everything"]);
+    }
+
+    #[test]
+    fn test_equal_scores_order() {
+        // Items equal in everything but their detail fall back to the
+        // server order, so the order is total and the same on every filter
+        let items = (0..100)
+            .map(|i| CompletionItem {
+                label: "foo".to_string(),
+                detail: Some(i.to_string()),
+                ..Default::default()
+            })
+            .collect();
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion.update_input("fo".to_string());
+        let order = |completion: &CompletionData| {
+            completion
+                .current_items()
+                .iter()
+                .map(|i| i.index())
+                .collect::<Vec<_>>()
+        };
+        let first = order(&completion);
+        assert_eq!(first, (0..100).collect::<Vec<_>>());
+
+        completion.update_input("foo".to_string());
+        assert_eq!(order(&completion), first);
+    }
+
+    #[test]
+    fn test_out_of_order_responses() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.request_id = 2;
+        completion.latest_request_id = 2;
+
+        completion.receive(
+            2,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("new")]),
+            None,
+        );
+        // The response to the earlier request arrives last
+        completion.receive(
+            1,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("old")]),
+            None,
+        );
+        assert_eq!(completion.current(), "new");
+
+        // Once cancelled, even a response to the latest request sent is
+        // older than whatever the next session sends
+        completion.cancel();
+        assert!(completion.latest_request_id > 2);
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            2,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("old")]),
+            None,
+        );
+        assert!(completion.is_empty());
+        completion.receive(
+            completion.latest_request_id,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("new")]),
+            None,
+        );
+        assert_eq!(completion.current(), "new");
+    }
+
+    #[test]
+    fn test_merge_items() {
+        let mut completion = completion_with_items(&["foo", "bar"]);
+        completion.update_input("ba".to_string());
+
+        completion.merge_items(
+            0,
+            vec![item("baz"), item("bar"), item("")],
+            Some("plugin".to_string()),
+        );
+        let labels: Vec<&str> = completion
+            .current_items()
+            .iter()
+            .map(|i| i.item.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["bar", "baz"]);
+        assert_eq!(completion.current_items()[0].source, None);
+        assert_eq!(
+            completion.current_items()[1].source.as_deref(),
+            Some("plugin")
+        );
+
+        // Items for a session that's been superseded are dropped
+        completion.merge_items(1, vec![item("bat")], None);
+        assert_eq!(completion.current_items().len(), 2);
+    }
+
+    #[test]
+    fn test_buffer_words() {
+        let buffer = Buffer::new("alpha beta_2 9lives x\nalpha gam beta_2\n");
+        // The cursor is at the end of `gam`
+        let words: Vec<String> = buffer_words(&buffer, 31)
+            .into_iter()
+            .map(|i| i.label)
+            .collect();
+        assert_eq!(words, vec!["beta_2", "alpha"]);
+    }
+
+    #[test]
+    fn test_buffer_words_fallback() {
+        let word = |label: &str| CompletionItem {
+            kind: Some(CompletionItemKind::Text),
+            ..item(label)
+        };
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.input = "be".to_string();
+        completion.set_buffer_words(vec![word("alpha"), word("beta")]);
+        assert_eq!(completion.current(), "beta");
+        assert_eq!(completion.current_item().source.as_deref(), Some("buffer"));
+
+        // Items from a server take their place
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("bet")]),
+            None,
+        );
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current(), "bet");
+
+        completion.cancel();
+        assert!(completion.all_items().is_empty());
+    }
+
+    #[test]
+    fn test_rows() {
+        let mut completion = completion_with_items(&["foo", "bar", "baz"]);
+        completion.update_input("ba".to_string());
+        completion.next();
+
+        let rows: Vec<CompletionRow> = completion.rows().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows.iter().map(|r| r.selected).collect::<Vec<_>>(),
+            vec![false, true]
+        );
+        assert_eq!(rows[1].label, completion.current());
+        assert_eq!(rows[1].indices, completion.current_indices());
+        assert!(rows.iter().all(|r| r.source.is_none() && !r.deprecated));
+    }
+
+    #[test]
+    fn test_row_at() {
+        let mut completion = completion_with_items(&["foo", "bar", "baz"]);
+        assert_eq!(completion.row_at(0.0, 20.0), Some(0));
+        assert_eq!(completion.row_at(45.0, 20.0), Some(2));
+        // Below the list
+        assert_eq!(completion.row_at(60.0, 20.0), None);
+        assert_eq!(completion.row_at(-1.0, 20.0), None);
+
+        completion.select(1);
+        assert_eq!(completion.index, 1);
+        completion.select(10);
+        assert_eq!(completion.index, 2);
+    }
+
+    #[test]
+    fn test_boundary_matches() {
+        assert_eq!(boundary_matches("fooBarBaz", &[0, 3, 6]), 3);
+        assert_eq!(boundary_matches("foobarbaz", &[0, 3, 6]), 1);
+        assert_eq!(boundary_matches("foo_bar_baz", &[0, 4, 8]), 3);
+        assert_eq!(boundary_matches("FOO", &[1, 2]), 0);
+    }
+
+    #[test]
+    fn test_boundary_bonus() {
+        let item = |label: &str, sort_text: &str| CompletionItem {
+            sort_text: Some(sort_text.to_string()),
+            ..item(label)
+        };
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        // The server ranks the flat name first
+        let items = vec![item("foobarbaz", "a"), item("fooBarBaz", "b")];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion.update_input("fbb".to_string());
+
+        completion.set_boundary_bonus(50);
+        assert_eq!(completion.current_items()[0].item.label, "fooBarBaz");
+        assert_eq!(completion.current_items()[1].item.label, "foobarbaz");
+    }
+
+    #[test]
+    fn test_prefix_match_first() {
+        let mut completion = completion_with_items(&["a_n_e_w", "Newt"]);
+        // Every matched character of `a_n_e_w` starts a word
+        completion.set_boundary_bonus(1000);
+        completion.update_input("new".to_string());
+
+        let items = completion.current_items();
+        assert_eq!(items[0].item.label, "Newt");
+        assert_eq!(items[1].item.label, "a_n_e_w");
+        assert!(items[1].score() > items[0].score());
+    }
+
+    #[test]
+    fn test_preferred_size() {
+        let labels: Vec<String> = (0..50).map(|i| format!("item{:02}", i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let mut completion = completion_with_items(&labels);
+        // Only the size limits the height here
+        completion.max_visible_rows = 0;
+        assert_eq!(completion.max_size(), Size::new(600.0, 300.0));
+        assert_eq!(completion.popup_size(10.0, 20.0).height, 300.0);
+
+        completion.set_preferred_size(None, Size::new(800.0, 400.0));
+        assert_eq!(completion.max_size(), Size::new(800.0, 400.0));
+        assert_eq!(completion.popup_size(10.0, 20.0).height, 400.0);
+    }
+
+    #[test]
+    fn test_current_indices() {
+        let mut completion = completion_with_items(&["foo_bar", "baz"]);
+        assert!(completion.current_indices().is_empty());
+
+        completion.update_input("fb".to_string());
+        assert_eq!(completion.current_indices(), &[0, 4]);
+        assert_eq!(completion.current_item().index(), 0);
+
+        completion.update_input("xyz".to_string());
+        assert!(completion.current_indices().is_empty());
+    }
+
+    #[test]
+    fn test_incremental_filter() {
+        let labels: Vec<String> =
+            (0..5000).map(|i| format!("item_{}_{}", i % 7, i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+
+        let mut narrowed = completion_with_items(&labels);
+        narrowed.update_input("i".to_string());
+        narrowed.update_input("it".to_string());
+        narrowed.update_input("it3".to_string());
+        narrowed.update_input("it31".to_string());
+
+        let mut full = completion_with_items(&labels);
+        full.update_input("it31".to_string());
+
+        assert!(!full.is_empty());
+        let matches = |completion: &CompletionData| {
+            completion
+                .current_items()
+                .iter()
+                .map(|i| (i.item.label.clone(), i.score, i.indices.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matches(&full), matches(&narrowed));
+
+        // Same input again hits the cached scores
+        narrowed.update_input("it31".to_string());
+        assert_eq!(matches(&full), matches(&narrowed));
+    }
+
+    #[test]
+    fn test_incremental_filter_min_score() {
+        let labels: Vec<String> =
+            (0..500).map(|i| format!("item_{}_{}", i % 7, i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let scores = |completion: &CompletionData| {
+            completion
+                .current_items()
+                .iter()
+                .map(|i| (i.item.label.clone(), i.score))
+                .collect::<Vec<_>>()
+        };
+
+        for min_score_per_char in [0, 8, 16, 24] {
+            let mut narrowed = completion_with_items(&labels);
+            narrowed.set_min_score_per_char(min_score_per_char);
+            narrowed.update_input("i".to_string());
+            narrowed.update_input("it".to_string());
+            narrowed.update_input("it3".to_string());
+            narrowed.update_input("it31".to_string());
+
+            let mut full = completion_with_items(&labels);
+            full.set_min_score_per_char(min_score_per_char);
+            full.update_input("it31".to_string());
+
+            assert_eq!(scores(&full), scores(&narrowed));
+        }
+    }
+
+    #[test]
+    fn test_score_cache() {
+        let mut completion = completion_with_items(&["foo_bar", "fob"]);
+        completion.update_input("fb".to_string());
+        assert_eq!(completion.len(), 2);
+        // The scores are kept on the received items, not just on the
+        // filtered copies
+        assert!(completion
+            .all_items()
+            .iter()
+            .all(|i| i.scored_input.as_deref() == Some("fb")));
+
+        // Match scores the matcher can't come up with, so that they only
+        // come back from the cache
+        let items = Arc::make_mut(completion.input_items.get_mut("").unwrap());
+        for item in items.iter_mut() {
+            item.match_score = if item.item.label == "fob" { 1 } else { 1000 };
+        }
+        completion.filter_items();
+        let scores = |completion: &CompletionData| {
+            completion
+                .current_items()
+                .iter()
+                .map(|i| (i.item.label.clone(), i.score))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            scores(&completion),
+            vec![("foo_bar".to_string(), 1000), ("fob".to_string(), 1)]
+        );
+
+        // The bonuses are added to a cached score as they are now
+        completion.set_boundary_bonus(50);
+        assert_eq!(
+            scores(&completion),
+            vec![("foo_bar".to_string(), 1100), ("fob".to_string(), 51)]
+        );
+
+        // And a cached score is held to the minimum as it is now
+        completion.set_min_score_per_char(100);
+        assert_eq!(scores(&completion), vec![("foo_bar".to_string(), 1100)]);
+
+        // Until the scores are forgotten
+        completion.set_min_score_per_char(0);
+        completion.set_case_matching(CaseMatching::Respect);
+        assert!(completion.current_items().iter().all(|i| i.score < 1000));
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let item = |label: &str, sort_text: &str| CompletionItem {
+            kind: Some(CompletionItemKind::Function),
+            detail: Some("fn()".to_string()),
+            sort_text: Some(sort_text.to_string()),
+            ..item(label)
+        };
+        let items = vec![item("abc", "1"), item("abd", "2"), item("abc", "0")];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+
+        let items = completion.current_items();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].item.label, "abc");
+        assert_eq!(items[0].sort_text, "0");
+        assert_eq!(items[1].item.label, "abd");
+    }
+
+    #[test]
+    fn test_suppressed_kinds() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let item = |label: &str, kind: CompletionItemKind| CompletionItem {
+            kind: Some(kind),
+            ..item(label)
+        };
+        let items = vec![
+            item("fn", CompletionItemKind::Snippet),
+            item("foo", CompletionItemKind::Function),
+            item("for", CompletionItemKind::Keyword),
+            item("format", CompletionItemKind::Snippet),
+        ];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+
+        completion.toggle_kind(CompletionItemKind::Snippet);
+        assert_eq!(sorted_labels(&completion), vec!["foo", "for"]);
+
+        completion.update_input("f".to_string());
+        assert_eq!(sorted_labels(&completion), vec!["foo", "for"]);
+
+        completion.toggle_kind(CompletionItemKind::Snippet);
+        assert_eq!(
+            sorted_labels(&completion),
+            vec!["fn", "foo", "for", "format"]
+        );
+    }
+
+    #[test]
+    fn test_kind_priority() {
+        assert_eq!(
+            parse_kind_priority("field, EnumMember,type-parameter, nope, field"),
+            vec![
+                CompletionItemKind::Field,
+                CompletionItemKind::EnumMember,
+                CompletionItemKind::TypeParameter
+            ]
+        );
+        assert!(parse_kind_priority("").is_empty());
+
+        let item = |label: &str, kind: Option<CompletionItemKind>| CompletionItem {
+            kind,
+            ..item(label)
+        };
+        let items = vec![
+            item("fooa", Some(CompletionItemKind::Keyword)),
+            item("foob", Some(CompletionItemKind::Field)),
+            item("fooc", Some(CompletionItemKind::Snippet)),
+            item("food", Some(CompletionItemKind::Method)),
+            item("fooe", None),
+        ];
+        let received = |kind_priority: &str| {
+            let mut completion = CompletionData::new();
+            completion.kind_priority = parse_kind_priority(kind_priority);
+            completion.status = CompletionStatus::Started;
+            completion.receive(
+                0,
+                "".to_string(),
+                CompletionResponse::Array(items.clone()),
+                None,
+            );
+            completion
+        };
+
+        // Without a priority, equal matches keep the server's order
+        let mut completion = received("");
+        completion.update_input("foo".to_string());
+        let scored = completion.current_items();
+        assert!(scored.iter().all(|i| i.score == scored[0].score));
+        assert_eq!(
+            labels(&completion),
+            vec!["fooa", "foob", "fooc", "food", "fooe"]
+        );
+
+        // The listed kinds come first, in order, then the rest as before,
+        // both with and without input
+        let mut completion = received("field, method, keyword");
+        assert_eq!(
+            labels(&completion),
+            vec!["foob", "food", "fooa", "fooc", "fooe"]
+        );
+        completion.update_input("foo".to_string());
+        assert_eq!(
+            labels(&completion),
+            vec!["foob", "food", "fooa", "fooc", "fooe"]
+        );
+    }
+
+    #[test]
+    fn test_recent() {
+        let mut completion = completion_with_items(&["foo_a", "foo_b"]);
+        completion.update_input("foo".to_string());
+        let items = completion.current_items();
+        assert_eq!(items[0].score, items[1].score);
+        assert_eq!(items[0].item.label, "foo_a");
+
+        completion.record_accepted(None, "foo_b");
+        completion.filter_items();
+        assert_eq!(completion.current_items()[0].item.label, "foo_b");
+
+        // The server order is kept when there's no input
+        completion.update_input("".to_string());
+        assert_eq!(completion.current_items()[0].item.label, "foo_a");
+    }
+
+    #[test]
+    fn test_kind_tag() {
+        assert_eq!(kind_tag(CompletionItemKind::Function), "fn");
+        assert_eq!(kind_tag(CompletionItemKind::Variable), "var");
+        assert_eq!(kind_tag(CompletionItemKind::TypeParameter), "T");
+        for kind in [
+            CompletionItemKind::EnumMember,
+            CompletionItemKind::Constructor,
+            CompletionItemKind::Snippet,
+        ] {
+            assert!((1..=3).contains(&kind_tag(kind).chars().count()));
+        }
+    }
+
+    #[test]
+    fn test_accessible_label() {
+        let mut completion = CompletionData::new();
+        assert_eq!(completion.accessible_label(), None);
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(vec![
+                CompletionItem {
+                    label: "foo".to_string(),
+                    kind: Some(CompletionItemKind::Method),
+                    detail: Some("fn foo() -> i32".to_string()),
+                    ..Default::default()
+                },
+                CompletionItem {
+                    label: "bar".to_string(),
+                    kind: Some(CompletionItemKind::TypeParameter),
+                    detail: Some(" ".to_string()),
+                    ..Default::default()
+                },
+                item("baz"),
+            ]),
+            None,
+        );
+        let labels = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let announced = labels.clone();
+        completion.set_on_announce(Some(Arc::new(move |label: &str| {
+            announced.lock().unwrap().push(label.to_string());
+        })));
+
+        for index in 0..3 {
+            completion.select(index);
+            completion.announce_selection();
+        }
+        assert_eq!(
+            *labels.lock().unwrap(),
+            vec![
+                "bar, type parameter, 1 of 3",
+                "baz, 2 of 3",
+                "foo, method, fn foo() -> i32, 3 of 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_context() {
+        let mut completion = CompletionData::new();
+        completion.trigger_kind = CompletionTriggerKind::TriggerCharacter;
+        completion.trigger_character = Some(".".to_string());
+        let context = completion.context();
+        assert_eq!(
+            context.trigger_kind,
+            CompletionTriggerKind::TriggerCharacter
+        );
+        assert_eq!(context.trigger_character.as_deref(), Some("."));
+
+        completion.refetch_incomplete();
+        let context = completion.context();
+        assert_eq!(
+            context.trigger_kind,
+            CompletionTriggerKind::TriggerForIncompleteCompletions
+        );
+        assert_eq!(context.trigger_character, None);
+    }
+
+    #[test]
+    fn test_scroll_documentation() {
+        let mut completion = completion_with_items(&["foo", "bar"]);
+        assert_eq!(completion.documentation_scroll(), 0.0);
+        completion.scroll_documentation(-50.0, 200.0);
+        assert_eq!(completion.documentation_scroll(), 0.0);
+        completion.scroll_documentation(150.0, 200.0);
+        completion.scroll_documentation(150.0, 200.0);
+        assert_eq!(completion.documentation_scroll(), 200.0);
+        // Documentation shorter than the popup doesn't scroll
+        completion.scroll_documentation(10.0, -20.0);
+        assert_eq!(completion.documentation_scroll(), 0.0);
+
+        completion.scroll_documentation(50.0, 200.0);
+        completion.next();
+        assert_eq!(completion.documentation_scroll(), 0.0);
+        completion.previous();
+        assert_eq!(completion.documentation_scroll(), 50.0);
+        completion.update_input("f".to_string());
+        assert_eq!(completion.documentation_scroll(), 0.0);
+    }
+
+    #[test]
+    fn test_max_stored_items() {
+        let mut completion = CompletionData::new();
+        completion.max_stored_items = 10;
+        completion.status = CompletionStatus::Started;
+        let items = (0..15)
+            .map(|i| CompletionItem {
+                label: format!("item_{}", i),
+                sort_text: Some(format!("{:02}", 14 - i)),
+                ..Default::default()
+            })
+            .collect();
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        assert_eq!(completion.len(), 10);
+        assert_eq!(completion.source_capped, 5);
+        // The ones that sort first are kept
+        assert_eq!(completion.current_items()[0].item.label, "item_14");
+        assert!(completion
+            .current_items()
+            .iter()
+            .all(|item| item.item.label != "item_0"));
+        assert_eq!(completion.more_label().as_deref(), Some("… 5 not loaded"));
+
+        completion.max_items = 4;
+        completion.update_input("item".to_string());
+        assert_eq!(
+            completion.more_label().as_deref(),
+            Some("… 6 more, 5 not loaded")
+        );
+
+        completion.cancel();
+        assert_eq!(completion.source_capped, 0);
+    }
+
+    #[test]
+    fn test_max_items() {
+        let labels: Vec<String> = (0..300).map(|i| format!("item_{}", i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let mut completion = completion_with_items(&labels);
+
+        completion.update_input("item".to_string());
+        assert_eq!(completion.len(), 200);
+        assert_eq!(completion.truncated, 100);
+
+        completion.update_input("item_29".to_string());
+        assert!(completion.len() < 200);
+        assert_eq!(completion.truncated, 0);
+        assert_eq!(completion.current_items()[0].item.label, "item_29");
+    }
+
+    #[test]
+    fn test_match_detail() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![CompletionItem {
+            label: "foo".to_string(),
+            detail: Some("Vec<String>".to_string()),
+            ..Default::default()
+        }];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+
+        completion.update_input("fvec".to_string());
+        assert!(completion.is_empty());
+
+        completion.match_detail = true;
+        completion.filter_items();
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current_item().indices, vec![0]);
+    }
+
+    #[test]
+    fn test_match_completeness() {
+        assert_eq!(match_completeness("fooBar", "fb"), 1.0);
+        assert_eq!(match_completeness("length", "LENGHT"), 5.0 / 6.0);
+        assert_eq!(match_completeness("foo", ""), 1.0);
+
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.match_detail = true;
+        let items = vec![CompletionItem {
+            label: "foo".to_string(),
+            detail: Some("Vec<String>".to_string()),
+            ..Default::default()
+        }];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        assert_eq!(completion.match_completeness, None);
+
+        completion.update_input("fo".to_string());
+        assert_eq!(completion.match_completeness, Some(1.0));
+
+        // Only the `f` is in the label, the rest matched the detail
+        completion.update_input("fvec".to_string());
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.match_completeness, Some(0.25));
+
+        completion.update_input("".to_string());
+        assert_eq!(completion.match_completeness, None);
+    }
+
+    #[test]
+    fn test_filter_text() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![
+            CompletionItem {
+                label: "print!(…)".to_string(),
+                filter_text: Some("println".to_string()),
+                insert_text: Some("println!(\"$1\")".to_string()),
+                insert_text_format: Some(InsertTextFormat::Snippet),
+                ..Default::default()
+            },
+            CompletionItem {
+                label: "self.len".to_string(),
+                filter_text: Some("len".to_string()),
+                ..Default::default()
+            },
+        ];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+
+        completion.update_input("pln".to_string());
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current_item().item.label, "print!(…)");
+        assert!(completion.current_item().indices.is_empty());
+
+        completion.update_input("len".to_string());
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current_item().indices, vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn test_filter_text_multibyte_label() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![CompletionItem {
+            label: "größe.len".to_string(),
+            filter_text: Some("len".to_string()),
+            ..Default::default()
+        }];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+
+        // The highlight is at the characters of the label, not its bytes
+        completion.update_input("len".to_string());
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current_item().indices, vec![6, 7, 8]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut completion = completion_with_items(&["abc", "abd"]);
+        completion.update_input("ab".to_string());
+        assert_eq!(completion.len(), 2);
+
+        completion.reset();
+        assert!(completion.status == CompletionStatus::Inactive);
+        assert_eq!(completion.len(), 0);
+        assert!(completion.filtered_items.is_empty());
+    }
+
+    #[test]
+    fn test_trigger_character() {
+        let mut completion = CompletionData::new();
+        let buffer_id = BufferId(1);
+        assert_eq!(
+            completion.trigger_character(buffer_id, "a."),
+            Some(".".to_string())
+        );
+        assert_eq!(completion.trigger_character(buffer_id, "a>"), None);
+
+        completion.trigger_characters.insert(
+            buffer_id,
+            vec![".".to_string(), ":".to_string(), "::".to_string()],
+        );
+        assert_eq!(
+            completion.trigger_character(buffer_id, "a::"),
+            Some("::".to_string())
+        );
+        assert_eq!(
+            completion.trigger_character(buffer_id, " :"),
+            Some(":".to_string())
+        );
+        assert_eq!(completion.trigger_character(buffer_id, "a("), None);
+    }
+
+    #[test]
+    fn test_deprecated() {
+        let items = vec![
+            item("a"),
+            CompletionItem {
+                label: "b".to_string(),
+                deprecated: Some(true),
+                ..Default::default()
+            },
+            CompletionItem {
+                label: "c".to_string(),
+                tags: Some(vec![CompletionItemTag::Deprecated]),
+                ..Default::default()
+            },
+        ];
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+
+        let deprecated: Vec<bool> = completion
+            .current_items()
+            .iter()
+            .map(|i| i.is_deprecated())
+            .collect();
+        assert_eq!(deprecated, vec![false, true, true]);
+    }
+
+    #[test]
+    fn test_max_visible_rows() {
+        let labels: Vec<String> = (0..50).map(|i| format!("item{:02}", i)).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let mut completion = completion_with_items(&labels);
+        completion.max_visible_rows = 12;
+        assert_eq!(completion.popup_size(10.0, 20.0).height, 240.0);
+        // The size still caps it when it fits fewer rows
+        assert_eq!(completion.popup_size(10.0, 30.0).height, 300.0);
+
+        completion.page_next(20.0);
+        assert_eq!(completion.index, 12);
+        // Navigating past the visible rows scrolls to the selection
+        assert_eq!(completion.selection_span(20.0, 240.0), (240.0, 260.0));
+
+        completion.max_visible_rows = 0;
+        assert_eq!(completion.popup_size(10.0, 20.0).height, 300.0);
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut completion = completion_with_items(&["if", "impl", "in"]);
+        assert!(completion.is_compact());
+        assert_eq!(completion.icon_width(20.0), 0.0);
+        assert_eq!(completion.popup_size(10.0, 20.0).width, 100.0);
+
+        completion.auto_compact = false;
+        assert_eq!(completion.icon_width(20.0), 20.0);
+        assert_eq!(completion.popup_size(10.0, 20.0).width, 200.0);
+        completion.auto_compact = true;
+
+        // A long label, or a detail, brings back the icon column
+        let mut completion =
+            completion_with_items(&["if", "impl", "implementation_detail"]);
+        assert!(!completion.is_compact());
+        completion.update_input("im".to_string());
+        assert!(!completion.is_compact());
+        completion.update_input("if".to_string());
+        assert!(completion.is_compact());
+
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![CompletionItem {
+            label: "if".to_string(),
+            detail: Some("keyword".to_string()),
+            ..Default::default()
+        }];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        assert!(!completion.is_compact());
+    }
+
+    #[test]
+    fn test_popup_size() {
+        let mut completion = completion_with_items(&["a", "abcdefghij"]);
+        completion.auto_compact = false;
+        assert_eq!(completion.content_chars, 10);
+        assert_eq!(completion.popup_size(10.0, 20.0), Size::new(200.0, 40.0));
+
+        let long = "a".repeat(100);
+        let mut completion = completion_with_items(&[long.as_str()]);
+        assert_eq!(completion.popup_size(10.0, 20.0), Size::new(600.0, 20.0));
+
+        completion.update_input("a".to_string());
+        assert_eq!(completion.content_chars, 100);
+    }
+
+    #[test]
+    fn test_accept() {
+        let buffer = Buffer::new("let a = fo\n");
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![CompletionItem {
+            label: "foo".to_string(),
+            insert_text: Some("foo($1)".to_string()),
+            insert_text_format: Some(InsertTextFormat::Snippet),
+            additional_text_edits: Some(vec![TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                new_text: "use foo;\n".to_string(),
+            }]),
+            ..Default::default()
+        }];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion.update_input("fo".to_string());
+
+        let edit = completion.accept(&buffer, &[10]).remove(0);
+        assert_eq!((edit.start, edit.end), (8, 10));
+        assert_eq!(edit.text, "foo()");
+        assert!(edit.snippet.is_some());
+        assert_eq!(
+            edit.additional_edits,
+            vec![(0, 0, "use foo;\n".to_string())]
+        );
+
+        completion.cancel();
+        assert!(completion.accept(&buffer, &[10]).is_empty());
+
+        // An item resolved after the session ended is still accepted,
+        // the way the editor does it
+        let resolved = item("foo");
+        let edits = completion.accept_item(&resolved, &buffer, &[10], None);
+        assert_eq!(edits.len(), 1);
+        assert_eq!((edits[0].start, edits[0].end), (8, 10));
+        assert_eq!(edits[0].text, "foo");
+    }
+
+    #[test]
+    fn test_resolve_item_data() {
+        let data = serde_json::json!({
+            "position": { "line": 0, "character": 10 },
+            "imports": [{ "full_import_path": "std::fmt" }],
+        });
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![
+            item("bar"),
+            CompletionItem {
+                label: "foo".to_string(),
+                data: Some(data.clone()),
+                ..Default::default()
+            },
+        ];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion.update_input("fo".to_string());
+
+        let item = completion.resolve_item().unwrap();
+        assert_eq!(item.label, "foo");
+        assert_eq!(item.data.as_ref(), Some(&data));
+        let params = serde_json::to_value(&item).unwrap();
+        assert_eq!(params["data"], data);
+
+        // A resolved item without the data doesn't take it away
+        completion.receive_resolve(
+            0,
+            1,
+            CompletionItem {
+                label: "foo".to_string(),
+                detail: Some("fn foo()".to_string()),
+                ..Default::default()
+            },
+        );
+        let item = completion.resolve_item().unwrap();
+        assert_eq!(item.detail.as_deref(), Some("fn foo()"));
+        assert_eq!(item.data, Some(data));
+
+        completion.cancel();
+        assert!(completion.resolve_item().is_none());
+    }
+
+    #[test]
+    fn test_accept_multiple_cursors() {
+        let buffer = Buffer::new("let a = fo\nlet b = f\n");
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![CompletionItem {
+            label: "foo".to_string(),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: Range::new(Position::new(0, 8), Position::new(0, 10)),
+                new_text: "foo".to_string(),
+            })),
+            additional_text_edits: Some(vec![TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                new_text: "use foo;\n".to_string(),
+            }]),
+            ..Default::default()
+        }];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion.update_input("fo".to_string());
+
+        // The second cursor has typed a shorter prefix, and the third is in
+        // the word of the first
+        let edits = completion.accept(&buffer, &[10, 20, 9]);
+        assert_eq!(edits.len(), 2);
+        assert_eq!((edits[0].start, edits[0].end), (8, 10));
+        assert_eq!((edits[1].start, edits[1].end), (19, 20));
+        assert_eq!(edits[0].text, "foo");
+        assert_eq!(edits[1].text, "foo");
+        assert_eq!(
+            edits[0].additional_edits,
+            vec![(0, 0, "use foo;\n".to_string())]
+        );
+        assert!(edits[1].additional_edits.is_empty());
+    }
+
+    #[test]
+    fn test_accept_multiple_cursors_text_after() {
+        let buffer = Buffer::new("let a = fo\nlet b = fbar\n");
+        let item = CompletionItem {
+            label: "foo".to_string(),
+            ..Default::default()
+        };
+
+        // The text after the second cursor is kept, like after the first
+        let edits = CompletionEdit::at_cursors(&item, &buffer, &[10, 20], &[]);
+        assert_eq!(edits.len(), 2);
+        assert_eq!((edits[0].start, edits[0].end), (8, 10));
+        assert_eq!((edits[1].start, edits[1].end), (19, 20));
+
+        // Unless the first replaces the rest of its word as well
+        let buffer = Buffer::new("let a = fox\nlet b = fbar\n");
+        let item = CompletionItem {
+            text_edit: Some(CompletionTextEdit::InsertAndReplace(
+                InsertReplaceEdit {
+                    new_text: "foo".to_string(),
+                    insert: Range::new(Position::new(0, 8), Position::new(0, 10)),
+                    replace: Range::new(Position::new(0, 8), Position::new(0, 11)),
+                },
+            )),
+            ..item
+        };
+        let edits = CompletionEdit::at_cursors(&item, &buffer, &[10, 21], &[]);
+        assert_eq!(edits.len(), 2);
+        assert_eq!((edits[0].start, edits[0].end), (8, 11));
+        assert_eq!((edits[1].start, edits[1].end), (20, 24));
+    }
+
+    #[test]
+    fn test_insert_parentheses() {
+        let buffer = Buffer::new("let a = fo\n");
+        let item = CompletionItem {
+            label: "foo".to_string(),
+            kind: Some(CompletionItemKind::Function),
+            ..Default::default()
+        };
+        let edit = CompletionEdit::new(&item, &buffer, 10, &[]);
+        assert_eq!(edit.text, "foo");
+        assert!(edit.snippet.is_none());
+
+        let edit = edit.with_parentheses(&item, &buffer);
+        assert_eq!((edit.start, edit.end), (8, 10));
+        assert_eq!(edit.text, "foo()");
+        assert_eq!(vec![(0, (12, 12))], edit.snippet.unwrap().tabs(edit.start));
+
+        let variable = CompletionItem {
+            kind: Some(CompletionItemKind::Variable),
+            ..item.clone()
+        };
+        let edit = CompletionEdit::new(&variable, &buffer, 10, &[])
+            .with_parentheses(&variable, &buffer);
+        assert_eq!(edit.text, "foo");
+        assert!(edit.snippet.is_none());
+
+        let snippet = CompletionItem {
+            insert_text: Some("foo($1)".to_string()),
+            insert_text_format: Some(InsertTextFormat::Snippet),
+            ..item
+        };
+        let edit = CompletionEdit::new(&snippet, &buffer, 10, &[])
+            .with_parentheses(&snippet, &buffer);
+        assert_eq!(edit.text, "foo()");
+        assert_eq!(
+            vec![(1, (12, 12)), (0, (13, 13))],
+            edit.snippet.unwrap().tabs(edit.start)
+        );
+    }
+
+    #[test]
+    fn test_insert_parentheses_before_paren() {
+        let buffer = Buffer::new("let a = fo(1)\n");
+        let mut completion = CompletionData::new();
+        completion.insert_parentheses = true;
+        completion.status = CompletionStatus::Started;
+        let items = vec![CompletionItem {
+            label: "foo".to_string(),
+            kind: Some(CompletionItemKind::Method),
+            ..Default::default()
+        }];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        completion.update_input("fo".to_string());
+
+        let edit = completion.accept(&buffer, &[10]).remove(0);
+        assert_eq!((edit.start, edit.end), (8, 10));
+        assert_eq!(edit.text, "foo");
+        assert!(edit.snippet.is_none());
+
+        completion.insert_parentheses = false;
+        let buffer = Buffer::new("let a = fo\n");
+        let edit = completion.accept(&buffer, &[10]).remove(0);
+        assert_eq!(edit.text, "foo");
+    }
+
+    #[test]
+    fn test_adjust_indentation() {
+        let buffer = Buffer::new("fn main() {\n    ma\n}\n");
+        let item = CompletionItem {
+            label: "match".to_string(),
+            insert_text: Some("match $1 {\n\t$0\n}".to_string()),
+            insert_text_format: Some(InsertTextFormat::Snippet),
+            insert_text_mode: Some(InsertTextMode::AdjustIndentation),
+            ..Default::default()
+        };
+        let indent_unit = buffer.indent_unit();
+        let edit = CompletionEdit::new(&item, &buffer, 18, &[]);
+        assert_eq!((edit.start, edit.end), (16, 18));
+        assert_eq!(edit.text, format!("match  {{\n    {indent_unit}\n    }}"));
+        assert_eq!(
+            edit.snippet.unwrap().tabs(edit.start),
+            vec![
+                (1, (22, 22)),
+                (0, (29 + indent_unit.len(), 29 + indent_unit.len()))
+            ]
+        );
+
+        // Inserted as is without the mode
+        let item = CompletionItem {
+            insert_text_mode: None,
+            ..item
+        };
+        let edit = CompletionEdit::new(&item, &buffer, 18, &[]);
+        assert_eq!(edit.text, "match  {\n\t\n}");
+    }
+
+    #[test]
+    fn test_insert_replace_edit() {
+        let item = |insert: Range, replace: Range| CompletionItem {
+            label: "foobar".to_string(),
+            text_edit: Some(CompletionTextEdit::InsertAndReplace(
+                InsertReplaceEdit {
+                    new_text: "foobar".to_string(),
+                    insert,
+                    replace,
+                },
+            )),
+            ..Default::default()
+        };
+
+        // In the middle of `foobaz`, which is replaced whole
+        let buffer = Buffer::new("let a = foobaz\n");
+        let item_in_word = item(
+            Range::new(Position::new(0, 8), Position::new(0, 11)),
+            Range::new(Position::new(0, 8), Position::new(0, 14)),
+        );
+        let edit = CompletionEdit::new(&item_in_word, &buffer, 11, &[]);
+        assert_eq!((edit.start, edit.end), (8, 14));
+
+        // Right after the `.`, where there's no word to replace
+        let buffer = Buffer::new("foo. bar\n");
+        let item_after_dot = item(
+            Range::new(Position::new(0, 4), Position::new(0, 4)),
+            Range::new(Position::new(0, 4), Position::new(0, 8)),
+        );
+        let edit = CompletionEdit::new(&item_after_dot, &buffer, 4, &[]);
+        assert_eq!((edit.start, edit.end), (4, 4));
+    }
+
+    #[test]
+    fn test_word_chars() {
+        let buffer = Buffer::new("a { background-c: red }");
+        let word = item("background-color");
+
+        // Without `-` in words, only the `c` would be replaced
+        let edit = CompletionEdit::new(&word, &buffer, 16, &[]);
+        assert_eq!((edit.start, edit.end), (15, 16));
+
+        let edit = CompletionEdit::new(&word, &buffer, 16, &['-']);
+        assert_eq!((edit.start, edit.end), (4, 16));
+        assert_eq!(edit.text, "background-color");
+
+        let buffer = Buffer::new("list.emp? x");
+        let word = item("empty?");
+        let edit = CompletionEdit::new(&word, &buffer, 8, &['?', '!']);
+        assert_eq!((edit.start, edit.end), (5, 9));
+    }
+
+    #[test]
+    fn test_keep_selection() {
+        let mut completion = completion_with_items(&["abc", "abd", "xyz"]);
+        completion.update_input("a".to_string());
+        completion.next();
+        assert_eq!(completion.current_item().item.label, "abd");
+
+        // Still matches, so it stays selected
+        completion.update_input("ab".to_string());
+        assert_eq!(completion.current_item().item.label, "abd");
+
+        // Gone, so the top item is selected
+        completion.update_input("abc".to_string());
+        assert_eq!(completion.index, 0);
+        assert_eq!(completion.current_item().item.label, "abc");
+    }
+
+    #[test]
+    fn test_from_setting() {
+        assert_eq!(CaseMatching::from_setting("Smart"), CaseMatching::Smart);
+        assert_eq!(
+            CaseMatching::from_setting(" respect"),
+            CaseMatching::Respect
+        );
+        assert_eq!(CaseMatching::from_setting("nope"), CaseMatching::Ignore);
+        assert_eq!(MatchStrategy::from_setting("prefix"), MatchStrategy::Prefix);
+        assert_eq!(
+            MatchStrategy::from_setting("SUBSTRING"),
+            MatchStrategy::Substring
+        );
+        assert_eq!(MatchStrategy::from_setting(""), MatchStrategy::Fuzzy);
+        assert_eq!(
+            CompletionDisplayMode::from_setting("inline"),
+            CompletionDisplayMode::Inline
+        );
+        assert_eq!(
+            CompletionDisplayMode::from_setting("list"),
+            CompletionDisplayMode::Popup
+        );
+    }
+
+    #[test]
+    fn test_smart_case() {
+        let mut completion = completion_with_items(&["Foo", "foo", "FooBar"]);
+        completion.update_input("Foo".to_string());
+        assert_eq!(sorted_labels(&completion), vec!["Foo", "FooBar", "foo"]);
+
+        completion.set_case_matching(CaseMatching::Smart);
+        assert_eq!(sorted_labels(&completion), vec!["Foo", "FooBar"]);
+
+        completion.update_input("foo".to_string());
+        assert_eq!(sorted_labels(&completion), vec!["Foo", "FooBar", "foo"]);
+    }
+
+    #[test]
+    fn test_debug_dump() {
+        let labels: Vec<String> = (0..12).map(|i| format!("foo{i}")).collect();
+        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+        let mut completion = completion_with_items(&labels);
+        completion.set_match_strategy(MatchStrategy::Prefix);
+        completion
+            .suppressed_kinds
+            .push(CompletionItemKind::Snippet);
+        completion.update_input("foo1".to_string());
+
+        let dump = completion.debug_dump();
+        assert!(dump
+            .starts_with("completion Started, request 0, offset 0, input \"foo1\""));
+        assert!(dump.contains("matching: Prefix, case Ignore"));
+        assert!(dump.contains("suppressed kinds: [Snippet]"));
+        assert!(dump.contains("items: 3 of 12"));
+        assert!(dump.contains("1. \"foo1\" score"));
+        assert!(dump.contains("indices [0, 1, 2, 3]"));
+
+        completion.update_input("f".to_string());
+        let dump = completion.debug_dump();
+        assert!(dump.contains(" 10. "));
+        assert!(!dump.contains(" 11. "));
+    }
+
+    #[test]
+    fn test_match_strategy() {
+        let matches = |completion: &CompletionData| {
+            completion
+                .current_items()
+                .iter()
+                .map(|i| (i.item.label.clone(), i.indices.clone()))
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+        let mut completion =
+            completion_with_items(&["barFoo", "fooBar", "fob", "xyz"]);
+        completion.update_input("fo".to_string());
+        assert_eq!(completion.match_strategy, MatchStrategy::Fuzzy);
+        assert_eq!(completion.len(), 3);
+
+        completion.update_input("fob".to_string());
+        assert_eq!(completion.len(), 2);
+
+        completion.set_match_strategy(MatchStrategy::Substring);
+        assert_eq!(
+            matches(&completion),
+            vec![("fob".to_string(), vec![0, 1, 2])]
+        );
+
+        completion.update_input("bar".to_string());
+        assert_eq!(
+            matches(&completion),
+            vec![
+                ("barFoo".to_string(), vec![0, 1, 2]),
+                ("fooBar".to_string(), vec![3, 4, 5]),
+            ]
+        );
+
+        completion.set_match_strategy(MatchStrategy::Prefix);
+        assert_eq!(
+            matches(&completion),
+            vec![("barFoo".to_string(), vec![0, 1, 2])]
+        );
+
+        completion.update_input("Foo".to_string());
+        assert_eq!(
+            matches(&completion),
+            vec![("fooBar".to_string(), vec![0, 1, 2])]
+        );
+        completion.set_case_matching(CaseMatching::Respect);
+        assert!(completion.is_empty());
+
+        completion.set_match_strategy(MatchStrategy::Substring);
+        assert_eq!(
+            matches(&completion),
+            vec![("barFoo".to_string(), vec![3, 4, 5])]
+        );
+    }
+
+    #[test]
+    fn test_min_score() {
+        let mut completion = completion_with_items(&["abc", "axxxxbxxxxc"]);
+        let strong = completion.matcher.fuzzy_match("abc", "abc").unwrap();
+        let weak = completion
+            .matcher
+            .fuzzy_match("axxxxbxxxxc", "abc")
+            .unwrap();
+        assert!(weak < strong);
+
+        completion.update_input("abc".to_string());
+        assert_eq!(completion.len(), 2);
+
+        // Halfway between the scores, for the two characters after the first
+        completion.set_min_score_per_char((strong + weak) / 4);
+        assert_eq!(completion.len(), 1);
+        assert_eq!(completion.current_item().item.label, "abc");
+
+        // A single character is still matched with any score
+        completion.update_input("a".to_string());
+        assert_eq!(completion.len(), 2);
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::List(CompletionList {
+                is_incomplete: true,
+                items: vec![item("abc"), item("abd")],
+            }),
+            None,
+        );
+        assert!(completion.is_incomplete);
+
+        // Not filtered locally, the server is asked instead
+        completion.update_input("ab".to_string());
+        assert!(completion.is_empty());
+
+        completion.receive(
+            0,
+            "ab".to_string(),
+            CompletionResponse::List(CompletionList {
+                is_incomplete: false,
+                items: vec![item("abd")],
+            }),
+            None,
+        );
+        assert!(!completion.is_incomplete);
+        assert_eq!(completion.len(), 1);
+
+        completion.update_input("abd".to_string());
+        assert_eq!(completion.len(), 1);
+    }
+
+    #[test]
+    fn test_fade() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("foo")]),
+            None,
+        );
+        assert_eq!(completion.alpha(), 0.0);
+        assert!(completion.is_fading());
+
+        let start = Instant::now();
+        assert!(completion.animate(start));
+        assert!(completion.animate(start + Duration::from_millis(40)));
+        assert!((completion.alpha() - 0.5).abs() < 1e-9);
+        // The fade doesn't hold up accepting
+        assert!(completion.is_active_and_nonempty());
+        assert!(!completion.animate(start + Duration::from_millis(80)));
+        assert_eq!(completion.alpha(), 1.0);
+        assert!(!completion.is_fading());
+
+        completion.cancel();
+        assert!(completion.is_fading_out());
+        let start = start + Duration::from_millis(100);
+        assert!(completion.animate(start));
+        assert!(completion.animate(start + Duration::from_millis(20)));
+        assert!((completion.alpha() - 0.75).abs() < 1e-9);
+        assert!(!completion.animate(start + Duration::from_millis(100)));
+        assert!(!completion.is_fading_out());
+
+        completion.fade_duration = Duration::ZERO;
+        assert_eq!(completion.alpha(), 0.0);
+        completion.status = CompletionStatus::Started;
+        completion.receive(
+            completion.request_id,
+            "".to_string(),
+            CompletionResponse::Array(vec![item("foo")]),
+            None,
+        );
+        assert_eq!(completion.alpha(), 1.0);
+        assert!(!completion.is_fading());
+    }
+
+    #[test]
+    fn test_from_item() {
+        let item = CompletionItem {
+            label: "foo".to_string(),
+            sort_text: Some("b".to_string()),
+            ..Default::default()
+        };
+        let scored = ScoredCompletionItem::from_item(item.clone());
+        assert_eq!(scored.sort_text, "b");
+        assert_eq!(scored.index(), 0);
+        assert_eq!(scored.source, None);
+
+        let scored = ScoredCompletionItem::from_item(item)
+            .with_index(3)
+            .with_source(Some(Arc::from("plugin")))
+            .with_sort_text("a");
+        assert_eq!(scored.sort_text, "a");
+        assert_eq!(scored.index(), 3);
+        assert_eq!(scored.source.as_deref(), Some("plugin"));
+    }
+
+    #[test]
+    fn test_typed_path() {
+        assert_eq!(typed_path(r#"let p = "./src/ma"#), Some("./src/ma"));
+        assert_eq!(typed_path("open('~/"), Some("~/"));
+        assert_eq!(typed_path(r#"a = "\"" + "../x"#), Some("../x"));
+        assert_eq!(typed_path(r#"let p = "./src" + x"#), None);
+        assert_eq!(typed_path(r#"let p = "src/ma"#), None);
+        assert_eq!(typed_path("let p = ./src/"), None);
+    }
+
+    #[test]
+    fn test_path_directory() {
+        let current = Path::new("/project/src");
+        let home = Path::new("/home/user");
+        assert_eq!(
+            path_directory("./lib/ma", Some(current), Some(home)),
+            Some(PathBuf::from("/project/src/./lib/"))
+        );
+        assert_eq!(
+            path_directory("~/.config/", Some(current), Some(home)),
+            Some(PathBuf::from("/home/user/.config/"))
+        );
+        assert_eq!(
+            path_directory("/etc/ho", None, None),
+            Some(PathBuf::from("/etc/"))
+        );
+        assert_eq!(path_directory("../x", None, Some(home)), None);
+    }
+
+    #[test]
+    fn test_path_items() {
+        let start = Position::new(0, 10);
+        let items = path_items(
+            vec![("src".to_string(), true), ("Cargo.toml".to_string(), false)],
+            start,
+        );
+        let new_text = |item: &CompletionItem| match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => {
+                assert_eq!(edit.range, Range::new(start, start));
+                edit.new_text.clone()
+            }
+            _ => panic!("expected an edit"),
+        };
+        assert_eq!(items[0].label, "Cargo.toml");
+        assert_eq!(items[0].kind, Some(CompletionItemKind::File));
+        assert_eq!(new_text(&items[0]), "Cargo.toml");
+        assert_eq!(items[1].label, "src");
+        assert_eq!(items[1].kind, Some(CompletionItemKind::Folder));
+        assert_eq!(new_text(&items[1]), "src/");
+    }
+
+    #[test]
+    fn test_preselect() {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = vec![
+            item("abc"),
+            CompletionItem {
+                label: "abd".to_string(),
+                preselect: Some(true),
+                ..Default::default()
+            },
+        ];
+        completion.receive(
+            0,
+            "".to_string(),
+            CompletionResponse::Array(items),
+            None,
+        );
+        assert_eq!("abd", completion.current());
+
+        completion.update_input("ab".to_string());
+        assert_eq!("abd", completion.current());
     }
 }
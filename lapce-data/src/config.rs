@@ -70,7 +70,20 @@ impl LapceTheme {
     pub const PALETTE_CURRENT: &'static str = "palette.current";
 
     pub const COMPLETION_BACKGROUND: &'static str = "completion.background";
+    pub const COMPLETION_BORDER: &'static str = "completion.border";
+    pub const COMPLETION_SHADOW: &'static str = "completion.shadow";
     pub const COMPLETION_CURRENT: &'static str = "completion.current";
+    pub const COMPLETION_CURRENT_ACCENT: &'static str = "completion.current.accent";
+    pub const COMPLETION_DETAIL: &'static str = "completion.detail";
+    pub const COMPLETION_MATCH_COLOR: &'static str = "completion.match";
+    pub const COMPLETION_KIND_FUNCTION: &'static str = "completion.kind.function";
+    pub const COMPLETION_KIND_TYPE: &'static str = "completion.kind.type";
+    pub const COMPLETION_KIND_FIELD: &'static str = "completion.kind.field";
+    pub const COMPLETION_KIND_VARIABLE: &'static str = "completion.kind.variable";
+    pub const COMPLETION_KIND_CONSTANT: &'static str = "completion.kind.constant";
+    pub const COMPLETION_KIND_KEYWORD: &'static str = "completion.kind.keyword";
+    pub const COMPLETION_KIND_MODULE: &'static str = "completion.kind.module";
+    pub const COMPLETION_KIND_TEXT: &'static str = "completion.kind.text";
 
     pub const HOVER_BACKGROUND: &'static str = "hover.background";
 
@@ -141,6 +154,66 @@ pub struct EditorConfig {
         desc = "How long (in ms) it should take before the hover information appears"
     )]
     pub hover_delay: u64,
+    #[field_names(desc = "The width of the border around the completion popup")]
+    pub completion_border_width: usize,
+    #[field_names(desc = "How far the shadow of the completion popup is blurred")]
+    pub completion_shadow_width: usize,
+    #[field_names(
+        desc = "If the characters of a completion item matching the input are highlighted"
+    )]
+    pub completion_match_highlight: bool,
+    #[field_names(
+        desc = "How long the completion popup takes to fade in and out, in milliseconds. Set it to 0 to show and hide the popup instantly."
+    )]
+    pub completion_fade_duration: usize,
+    #[field_names(
+        desc = "The most completion items kept from a single language server response. Larger lists are cut down to the items that sort first."
+    )]
+    pub completion_max_stored_items: usize,
+    #[field_names(
+        desc = "The most items the completion popup shows before it scrolls. Set it to 0 to only limit the popup by its size."
+    )]
+    pub completion_max_visible_rows: usize,
+    #[field_names(
+        desc = "If the kind of a completion item is shown as an icon. When off, it's shown as a short text tag like fn or var."
+    )]
+    pub completion_kind_icons: bool,
+    #[field_names(
+        desc = "If accepting the completion of a function or method adds a pair of parentheses after it, with the cursor between them. Nothing is added when a parenthesis already follows."
+    )]
+    pub completion_insert_parentheses: bool,
+    #[field_names(
+        desc = "If the completion popup leaves out the kind icons and gets narrower when all its items are short and have no detail, like keywords."
+    )]
+    pub completion_auto_compact: bool,
+    #[field_names(
+        desc = "If a completion item is accepted as soon as it's the only one left and the same as what's typed, ignoring case, without pressing Enter."
+    )]
+    pub completion_auto_accept_exact: bool,
+    #[field_names(
+        desc = "If typing \".\" or \"::\" right after accepting the completion of a method or field opens completion again, even when the language server doesn't list it as a trigger character."
+    )]
+    pub completion_chain_after_accept: bool,
+    #[field_names(
+        desc = "The completion item kinds that are always ranked above the others, in this order and comma separated, like \"field, method\". When empty, the items are ranked by how well they match only."
+    )]
+    pub completion_kind_priority: String,
+    #[field_names(
+        desc = "How the case of what's typed is taken into account when matching completion items: \"ignore\", \"smart\" to only match case when it has an uppercase letter, or \"respect\"."
+    )]
+    pub completion_case_matching: String,
+    #[field_names(
+        desc = "How what's typed is matched against the completion items: \"fuzzy\", \"substring\" or \"prefix\"."
+    )]
+    pub completion_match_strategy: String,
+    #[field_names(
+        desc = "The score added to a completion item for each matched character at the start of a word in it, like the `b` of `fooBar` or `foo_bar`, so that those rank higher."
+    )]
+    pub completion_boundary_bonus: usize,
+    #[field_names(
+        desc = "How the completion items are shown: \"popup\", with a row per item, or \"inline\", with a page of items on a single row."
+    )]
+    pub completion_display_mode: String,
 }
 
 impl EditorConfig {
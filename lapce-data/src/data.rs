@@ -513,7 +513,9 @@ impl LapceTabData {
             event_sink.clone(),
         ));
         let palette = Arc::new(PaletteData::new(proxy.clone()));
-        let completion = Arc::new(CompletionData::new());
+        let mut completion = CompletionData::new();
+        completion.update_config(&config);
+        let completion = Arc::new(completion);
         let hover = Arc::new(HoverData::new());
         let source_control = Arc::new(SourceControlData::new());
         let settings = Arc::new(LapceSettingsPanelData::new());
@@ -841,58 +843,63 @@ impl LapceTabData {
         }
     }
 
-    pub fn completion_origin(
+    /// Where the completion popup goes and how large it can be, and
+    /// whether it's above the cursor line. It's placed below the line,
+    /// unless there's not enough room there and there's more above.
+    /// The height is cut to fit the side it's on, the rest is scrolled.
+    pub fn completion_rect(
         &self,
         text: &mut PietText,
         tab_size: Size,
         config: &Config,
-    ) -> Point {
+    ) -> (Rect, bool) {
         let line_height = self.config.editor.line_height as f64;
+        let width = config.editor_char_width(text);
+        let size = self.completion.popup_size(width, line_height);
 
         let editor = self.main_split.active_editor();
         let editor = match editor {
             Some(editor) => editor,
-            None => return Point::ZERO,
+            None => return (size.to_rect(), false),
         };
 
         match &editor.content {
-            BufferContent::Local(_) => {
-                *editor.window_origin.borrow()
-                    - self.window_origin.borrow().to_vec2()
-            }
-            BufferContent::Value(_) => {
-                *editor.window_origin.borrow()
-                    - self.window_origin.borrow().to_vec2()
+            BufferContent::Local(_) | BufferContent::Value(_) => {
+                let origin = *editor.window_origin.borrow()
+                    - self.window_origin.borrow().to_vec2();
+                (size.to_rect().with_origin(origin), false)
             }
             BufferContent::File(_) | BufferContent::Scratch(_) => {
                 let doc = self.main_split.editor_doc(editor.view_id);
                 let offset = self.completion.offset;
                 let (line, col) = doc.buffer().offset_to_line_col(offset);
-                let width = config.editor_char_width(text);
                 let x = col as f64 * width - line_height - 5.0;
                 let y = (line + 1) as f64 * line_height;
                 let mut origin = *editor.window_origin.borrow()
                     - self.window_origin.borrow().to_vec2()
                     + Vec2::new(x, y);
-                if origin.y + self.completion.size.height + 1.0 > tab_size.height {
-                    let height = self
-                        .completion
-                        .size
-                        .height
-                        .min(self.completion.len() as f64 * line_height);
-                    origin.y = editor.window_origin.borrow().y
-                        - self.window_origin.borrow().y
-                        + line as f64 * line_height
-                        - height;
+
+                let mut height = size.height;
+                let below = tab_size.height - origin.y - 1.0;
+                let above = origin.y - line_height;
+                let is_above = height > below && above > below;
+                if is_above {
+                    height = height.min(above);
+                    origin.y = above - height;
+                } else {
+                    height = height.min(below.max(0.0));
                 }
-                if origin.x + self.completion.size.width + 1.0 > tab_size.width {
-                    origin.x = tab_size.width - self.completion.size.width - 1.0;
+
+                if origin.x + size.width + 1.0 > tab_size.width {
+                    origin.x = tab_size.width - size.width - 1.0;
                 }
                 if origin.x <= 0.0 {
                     origin.x = 0.0;
                 }
 
-                origin
+                let rect =
+                    Size::new(size.width, height).to_rect().with_origin(origin);
+                (rect, is_above)
             }
         }
     }
@@ -1,7 +1,10 @@
 use crate::command::LapceCommand;
 use crate::command::LAPCE_COMMAND;
 use crate::command::{CommandExecuted, CommandKind};
-use crate::completion::{CompletionData, CompletionStatus, Snippet};
+use crate::completion::{
+    buffer_words, path_directory, typed_path, CompletionData, CompletionEdit,
+    CompletionStatus,
+};
 use crate::config::Config;
 use crate::data::{
     EditorDiagnostic, InlineFindDirection, LapceEditorData, LapceMainSplitData,
@@ -36,10 +39,9 @@ use lapce_core::command::{
 };
 use lapce_core::mode::{Mode, MotionMode};
 pub use lapce_core::syntax::Syntax;
-use lsp_types::CompletionTextEdit;
 use lsp_types::{
-    CodeActionResponse, CompletionItem, DiagnosticSeverity, GotoDefinitionResponse,
-    Location, Position,
+    CodeActionResponse, CompletionItem, CompletionItemKind, CompletionTriggerKind,
+    DiagnosticSeverity, GotoDefinitionResponse, Location, Position,
 };
 use serde_json::Value;
 use std::cmp::Ordering;
@@ -203,150 +205,188 @@ impl LapceEditorBufferData {
 
     /// Check if there are completions that are being rendered
     fn has_completions(&self) -> bool {
-        self.completion.status != CompletionStatus::Inactive
-            && self.completion.len() > 0
+        self.completion.is_active_and_nonempty()
     }
 
     fn has_hover(&self) -> bool {
         self.hover.status != HoverStatus::Inactive && !self.hover.is_empty()
     }
 
+    /// Apply a completion item to the buffer. The primary edit and the
+    /// `additional_text_edits` are applied as a single delta, so they are
+    /// undone together.
     pub fn apply_completion_item(&mut self, item: &CompletionItem) -> Result<()> {
-        let additional_edit: Option<Vec<_>> =
-            item.additional_text_edits.as_ref().map(|edits| {
-                edits
-                    .iter()
-                    .map(|edit| {
-                        let selection = lapce_core::selection::Selection::region(
-                            self.doc.buffer().offset_of_position(&edit.range.start),
-                            self.doc.buffer().offset_of_position(&edit.range.end),
-                        );
-                        (selection, edit.new_text.as_str())
-                    })
-                    .collect::<Vec<(lapce_core::selection::Selection, &str)>>()
-            });
-        let additioal_edit: Option<Vec<_>> = additional_edit.as_ref().map(|edits| {
-            edits.iter().map(|(selection, c)| (selection, *c)).collect()
-        });
-
-        let text_format = item
-            .insert_text_format
-            .unwrap_or(lsp_types::InsertTextFormat::PlainText);
-        if let Some(edit) = &item.text_edit {
-            match edit {
-                CompletionTextEdit::Edit(edit) => {
-                    let offset = self.editor.new_cursor.offset();
-                    let start_offset = self.doc.buffer().prev_code_boundary(offset);
-                    let end_offset = self.doc.buffer().next_code_boundary(offset);
-                    let edit_start =
-                        self.doc.buffer().offset_of_position(&edit.range.start);
-                    let edit_end =
-                        self.doc.buffer().offset_of_position(&edit.range.end);
-                    let selection = lapce_core::selection::Selection::region(
-                        start_offset.min(edit_start),
-                        end_offset.max(edit_end),
-                    );
-                    match text_format {
-                        lsp_types::InsertTextFormat::PlainText => {
-                            let (delta, inval_lines) = Arc::make_mut(&mut self.doc)
-                                .do_raw_edit(
-                                    &[
-                                        &[(&selection, edit.new_text.as_str())][..],
-                                        &additioal_edit.unwrap_or_default()[..],
-                                    ]
-                                    .concat(),
-                                    lapce_core::editor::EditType::InsertChars,
-                                );
-                            let selection = selection.apply_delta(
-                                &delta,
-                                true,
-                                lapce_core::selection::InsertDrift::Default,
-                            );
-                            Arc::make_mut(&mut self.editor)
-                                .new_cursor
-                                .update_selection(self.doc.buffer(), selection);
-                            self.apply_deltas(&[(delta, inval_lines)]);
-                            return Ok(());
-                        }
-                        lsp_types::InsertTextFormat::Snippet => {
-                            let snippet = Snippet::from_str(&edit.new_text)?;
-                            let text = snippet.text();
-                            let (delta, inval_lines) = Arc::make_mut(&mut self.doc)
-                                .do_raw_edit(
-                                    &[
-                                        &[(&selection, text.as_str())][..],
-                                        &additioal_edit.unwrap_or_default()[..],
-                                    ]
-                                    .concat(),
-                                    lapce_core::editor::EditType::InsertChars,
-                                );
-                            let selection = selection.apply_delta(
-                                &delta,
-                                true,
-                                lapce_core::selection::InsertDrift::Default,
-                            );
+        let language = self.doc.syntax().map(|s| s.language);
+        Arc::make_mut(&mut self.completion).record_accepted(language, &item.label);
 
-                            let mut transformer = Transformer::new(&delta);
-                            let offset = transformer
-                                .transform(start_offset.min(edit_start), false);
-                            let snippet_tabs = snippet.tabs(offset);
-
-                            if snippet_tabs.is_empty() {
-                                Arc::make_mut(&mut self.editor)
-                                    .new_cursor
-                                    .update_selection(self.doc.buffer(), selection);
-                                self.apply_deltas(&[(delta, inval_lines)]);
-                                return Ok(());
-                            }
+        // The cursor the completion was started at comes first
+        let offset = self.editor.new_cursor.offset();
+        let mut offsets = vec![offset];
+        if let lapce_core::cursor::CursorMode::Insert(selection) =
+            &self.editor.new_cursor.mode
+        {
+            offsets.extend(
+                selection
+                    .regions()
+                    .iter()
+                    .map(|region| region.end)
+                    .filter(|end| *end != offset),
+            );
+        }
+        let edits =
+            self.completion
+                .accept_item(item, self.doc.buffer(), &offsets, language);
+        self.apply_completion_edits(&edits);
+        let buffer_id = self.doc.id();
+        let offset = self.editor.new_cursor.offset();
+        Arc::make_mut(&mut self.completion).accepted_member(item, buffer_id, offset);
+        Ok(())
+    }
 
-                            let mut selection =
-                                lapce_core::selection::Selection::new();
-                            let (_tab, (start, end)) = &snippet_tabs[0];
-                            let region = lapce_core::selection::SelRegion::new(
-                                *start, *end, None,
-                            );
-                            selection.add_region(region);
-                            Arc::make_mut(&mut self.editor)
-                                .new_cursor
-                                .set_insert(selection);
-                            self.apply_deltas(&[(delta, inval_lines)]);
-                            Arc::make_mut(&mut self.editor)
-                                .add_snippet_placeholders(snippet_tabs);
-                            return Ok(());
-                        }
-                    }
+    /// Make the `edits` of a completion, one for each cursor, as a single
+    /// delta. With a single cursor, the tab stops of a snippet can be
+    /// moved through. With more, each cursor is just put at the first tab
+    /// stop of its edit, or at its end.
+    fn apply_completion_edits(&mut self, edits: &[CompletionEdit]) {
+        if edits.is_empty() {
+            return;
+        }
+        let selections: Vec<_> = edits
+            .iter()
+            .map(|edit| {
+                lapce_core::selection::Selection::region(edit.start, edit.end)
+            })
+            .collect();
+        let additional_edits: Vec<_> = edits
+            .iter()
+            .flat_map(|edit| edit.additional_edits.iter())
+            .map(|(start, end, text)| {
+                (
+                    lapce_core::selection::Selection::region(*start, *end),
+                    text.as_str(),
+                )
+            })
+            .collect();
+        let mut raw_edits: Vec<_> = selections
+            .iter()
+            .zip(edits)
+            .map(|(selection, edit)| (selection, edit.text.as_str()))
+            .collect();
+        raw_edits.extend(additional_edits.iter().map(|(s, text)| (s, *text)));
+
+        let (delta, inval_lines) = Arc::make_mut(&mut self.doc)
+            .do_raw_edit(&raw_edits, lapce_core::editor::EditType::InsertChars);
+
+        let edit = match edits {
+            [edit] => edit,
+            _ => {
+                let mut selection = lapce_core::selection::Selection::new();
+                for edit in edits {
+                    let mut transformer = Transformer::new(&delta);
+                    let start = transformer.transform(edit.start, false);
+                    let end = start + edit.text.len();
+                    let (start, end) = edit
+                        .snippet
+                        .as_ref()
+                        .and_then(|snippet| snippet.tabs(start).first().copied())
+                        .map(|(_tab, range)| range)
+                        .unwrap_or((end, end));
+                    selection.add_region(lapce_core::selection::SelRegion::new(
+                        start, end, None,
+                    ));
                 }
-                CompletionTextEdit::InsertAndReplace(_) => (),
+                Arc::make_mut(&mut self.editor)
+                    .new_cursor
+                    .set_insert(selection);
+                self.apply_deltas(&[(delta, inval_lines)]);
+                return;
             }
+        };
+        let selection = &selections[0];
+
+        let snippet_tabs = edit
+            .snippet
+            .as_ref()
+            .map(|snippet| {
+                let mut transformer = Transformer::new(&delta);
+                snippet.tabs(transformer.transform(edit.start, false))
+            })
+            .unwrap_or_default();
+
+        if snippet_tabs.is_empty() {
+            let selection = selection.apply_delta(
+                &delta,
+                true,
+                lapce_core::selection::InsertDrift::Default,
+            );
+            Arc::make_mut(&mut self.editor)
+                .new_cursor
+                .update_selection(self.doc.buffer(), selection);
+            self.apply_deltas(&[(delta, inval_lines)]);
+            return;
         }
 
-        let offset = self.editor.new_cursor.offset();
-        let start_offset = self.doc.buffer().prev_code_boundary(offset);
-        let end_offset = self.doc.buffer().next_code_boundary(offset);
-        let selection =
-            lapce_core::selection::Selection::region(start_offset, end_offset);
-
-        let (delta, inval_lines) = Arc::make_mut(&mut self.doc).do_raw_edit(
-            &[
-                &[(
-                    &selection,
-                    item.insert_text.as_deref().unwrap_or(item.label.as_str()),
-                )][..],
-                &additioal_edit.unwrap_or_default()[..],
-            ]
-            .concat(),
-            lapce_core::editor::EditType::InsertChars,
-        );
-        let selection = selection.apply_delta(
-            &delta,
-            true,
-            lapce_core::selection::InsertDrift::Default,
-        );
+        let mut selection = lapce_core::selection::Selection::new();
+        let (_tab, (start, end)) = &snippet_tabs[0];
+        let region = lapce_core::selection::SelRegion::new(*start, *end, None);
+        selection.add_region(region);
         Arc::make_mut(&mut self.editor)
             .new_cursor
-            .update_selection(self.doc.buffer(), selection);
+            .set_insert(selection);
         self.apply_deltas(&[(delta, inval_lines)]);
-        Ok(())
+        Arc::make_mut(&mut self.editor).add_snippet_placeholders(snippet_tabs);
+    }
+
+    /// Accept the selected completion item, resolving it first if the
+    /// server left out its additional edits
+    fn accept_completion(&mut self, ctx: &mut EventCtx) {
+        self.accept_completion_then(ctx, None);
+    }
+
+    /// Accept the selected completion item like `accept_completion`, then
+    /// type `then`, like the commit character that accepted it. It waits
+    /// for the item to be resolved, so that it comes after the item.
+    fn accept_completion_then(&mut self, ctx: &mut EventCtx, then: Option<&str>) {
+        let item = self.completion.current_item().item.to_owned();
+        self.completion.notify_accepted();
+        self.cancel_completion();
+        if item.data.is_some() && item.additional_text_edits.is_none() {
+            let view_id = self.editor.view_id;
+            let buffer_id = self.doc.id();
+            let rev = self.doc.rev();
+            let offset = self.editor.new_cursor.offset();
+            let then = then.map(|then| then.to_string());
+            let event_sink = ctx.get_external_handle();
+            self.proxy.completion_resolve(
+                buffer_id,
+                item.clone(),
+                Box::new(move |result| {
+                    let mut item = item.clone();
+                    if let Ok(res) = result {
+                        if let Ok(i) = serde_json::from_value::<CompletionItem>(res)
+                        {
+                            item = i;
+                        }
+                    };
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ResolveCompletion(
+                            buffer_id,
+                            rev,
+                            offset,
+                            Box::new(item),
+                            then.clone(),
+                        ),
+                        Target::Widget(view_id),
+                    );
+                }),
+            );
+        } else {
+            let _ = self.apply_completion_item(&item);
+            if let Some(then) = then {
+                self.receive_char(ctx, then);
+            }
+        }
     }
 
     pub fn cancel_completion(&mut self) {
@@ -354,12 +394,23 @@ impl LapceEditorBufferData {
         completion.cancel();
     }
 
+    /// Drop the completion session if it belongs to this buffer
+    fn reset_completion(&mut self) {
+        if self.completion.buffer_id == self.doc.id() {
+            let completion = Arc::make_mut(&mut self.completion);
+            completion.reset();
+        }
+    }
+
     pub fn cancel_hover(&mut self) {
         let hover = Arc::make_mut(&mut self.hover);
         hover.cancel();
     }
 
-    fn update_completion(&mut self, ctx: &mut EventCtx) {
+    /// Start or continue the completion session for the word at the cursor.
+    /// `invoked` is for when it's asked for with `get_completion`, which
+    /// starts a session even without a word typed.
+    fn update_completion(&mut self, ctx: &mut EventCtx, invoked: bool) {
         if self.get_mode() != Mode::Insert {
             self.cancel_completion();
             return;
@@ -371,23 +422,61 @@ impl LapceEditorBufferData {
             return;
         }
         let offset = self.editor.new_cursor.offset();
-        let start_offset = self.doc.buffer().prev_code_boundary(offset);
-        let end_offset = self.doc.buffer().next_code_boundary(offset);
+        // Deleting within the input keeps filtering, down to an empty input
+        // after a trigger character, but deleting past it is the end
+        if !invoked && self.completion.is_behind(self.doc.id(), offset) {
+            self.cancel_completion();
+            return;
+        }
+        let path_completion = self.path_completion(offset);
+        // Like `-` in html, some languages have more than the usual
+        // characters in their identifiers
+        let word_chars = self
+            .doc
+            .syntax()
+            .map(|s| s.language.word_chars())
+            .unwrap_or_default();
+        // In a path, the whole file name is completed, even when it's more
+        // than one word, like `main.rs`
+        let start_offset = match path_completion.as_ref() {
+            Some((name_start, _)) => *name_start,
+            None => self
+                .doc
+                .buffer()
+                .prev_code_boundary_with(offset, word_chars),
+        };
+        let end_offset = self
+            .doc
+            .buffer()
+            .next_code_boundary_with(offset, word_chars);
         let input = self
             .doc
             .buffer()
             .slice_to_cow(start_offset..end_offset)
             .to_string();
-        let char = if start_offset == 0 {
-            "".to_string()
-        } else {
-            self.doc
-                .buffer()
-                .slice_to_cow(start_offset - 1..start_offset)
-                .to_string()
-        };
+        // The two characters before the word, which is enough to find
+        // multi-character trigger characters like `::`
+        let prefix_offset =
+            self.doc.buffer().prev_grapheme_offset(start_offset, 2, 0);
+        let prefix = self
+            .doc
+            .buffer()
+            .slice_to_cow(prefix_offset..start_offset)
+            .to_string();
         let completion = Arc::make_mut(&mut self.completion);
-        if input.is_empty() && char != "." && char != ":" {
+        // A member accessor right after an accepted method or field, like
+        // the `.` in `foo.bar.`, chains on it even when the server doesn't
+        // count it as a trigger character
+        let chained =
+            completion.chained_trigger(self.doc.id(), start_offset, &prefix);
+        let trigger_character = completion
+            .trigger_character(self.doc.id(), &prefix)
+            .or(chained);
+        if input.is_empty()
+            && trigger_character.is_none()
+            && path_completion.is_none()
+            && !invoked
+        {
             completion.cancel();
             return;
         }
@@ -397,6 +486,13 @@ impl LapceEditorBufferData {
             && completion.buffer_id == self.doc.id()
         {
             completion.update_input(input.clone());
+            completion
+                .resolve_current(self.proxy.clone(), ctx.get_external_handle());
+
+            // The directory's entries are all there is to filter
+            if path_completion.is_some() {
+                return;
+            }
 
             if !completion.input_items.contains_key("") {
                 let event_sink = ctx.get_external_handle();
@@ -411,7 +507,12 @@ impl LapceEditorBufferData {
                 );
             }
 
-            if !completion.input_items.contains_key(&input) {
+            if !completion.input_items.contains_key(&input)
+                || completion.is_incomplete
+            {
+                if completion.is_incomplete {
+                    completion.refetch_incomplete();
+                }
                 let event_sink = ctx.get_external_handle();
                 completion.request(
                     self.proxy.clone(),
@@ -428,11 +529,37 @@ impl LapceEditorBufferData {
         }
 
         completion.buffer_id = self.doc.id();
+        completion.language = self.doc.syntax().map(|s| s.language);
+        // Asking for completion right after a trigger character is still
+        // a manual invocation, which servers may answer more broadly
+        let trigger_character = trigger_character.filter(|_| !invoked);
+        completion.trigger_kind = if trigger_character.is_some() {
+            CompletionTriggerKind::TriggerCharacter
+        } else {
+            CompletionTriggerKind::Invoked
+        };
+        completion.trigger_character = trigger_character;
         completion.offset = start_offset;
         completion.input = input.clone();
         completion.status = CompletionStatus::Started;
         completion.input_items.clear();
         completion.request_id += 1;
+        if let Some((_, dir)) = path_completion {
+            completion.request_paths(
+                self.proxy.clone(),
+                completion.request_id,
+                dir,
+                self.doc.buffer().offset_to_position(start_offset),
+                completion.id,
+                ctx.get_external_handle(),
+            );
+            return;
+        }
+        // Typing a word without a language server still gets completed
+        // from the words around it
+        if completion.trigger_character.is_none() {
+            completion.set_buffer_words(buffer_words(self.doc.buffer(), offset));
+        }
         let event_sink = ctx.get_external_handle();
         completion.request(
             self.proxy.clone(),
@@ -456,6 +583,25 @@ impl LapceEditorBufferData {
         }
     }
 
+    /// The start of the file name being typed and the directory to
+    /// complete it from, when the cursor is in a string literal that holds
+    /// a path
+    fn path_completion(&self, offset: usize) -> Option<(usize, PathBuf)> {
+        let buffer = self.doc.buffer();
+        let line_start = buffer.offset_of_line(buffer.line_of_offset(offset));
+        let line = buffer.slice_to_cow(line_start..offset);
+        let path = typed_path(&line)?;
+        let name_len = path.len() - path.rfind('/')? - 1;
+        let current_dir = match self.doc.content() {
+            BufferContent::File(path) => path.parent().map(Path::to_path_buf),
+            _ => None,
+        };
+        let home =
+            directories::UserDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+        let dir = path_directory(path, current_dir.as_deref(), home.as_deref())?;
+        Some((offset - name_len, dir))
+    }
+
     /// return true if there's existing hover and it's not changed
     pub fn check_hover(
         &mut self,
@@ -1211,7 +1357,7 @@ impl LapceEditorBufferData {
             }
         }
 
-        self.update_completion(ctx);
+        self.update_completion(ctx, false);
         self.apply_deltas(&deltas);
 
         CommandExecuted::Yes
@@ -1297,9 +1443,11 @@ impl LapceEditorBufferData {
                 }
             }
             SplitClose => {
+                self.reset_completion();
                 self.main_split.editor_close(ctx, self.view_id, false);
             }
             ForceExit => {
+                self.reset_completion();
                 self.main_split.editor_close(ctx, self.view_id, true);
             }
             SearchWholeWordForward => {
@@ -1469,41 +1617,7 @@ impl LapceEditorBufferData {
                         Target::Widget(self.palette.widget_id),
                     ));
                 } else {
-                    let item = self.completion.current_item().to_owned();
-                    self.cancel_completion();
-                    if item.data.is_some() {
-                        let view_id = self.editor.view_id;
-                        let buffer_id = self.doc.id();
-                        let rev = self.doc.rev();
-                        let offset = self.editor.new_cursor.offset();
-                        let event_sink = ctx.get_external_handle();
-                        self.proxy.completion_resolve(
-                            buffer_id,
-                            item.clone(),
-                            Box::new(move |result| {
-                                let mut item = item.clone();
-                                if let Ok(res) = result {
-                                    if let Ok(i) =
-                                        serde_json::from_value::<CompletionItem>(res)
-                                    {
-                                        item = i;
-                                    }
-                                };
-                                let _ = event_sink.submit_command(
-                                    LAPCE_UI_COMMAND,
-                                    LapceUICommand::ResolveCompletion(
-                                        buffer_id,
-                                        rev,
-                                        offset,
-                                        Box::new(item),
-                                    ),
-                                    Target::Widget(view_id),
-                                );
-                            }),
-                        );
-                    } else {
-                        let _ = self.apply_completion_item(&item);
-                    }
+                    self.accept_completion(ctx);
                 }
             }
             ListNext => {
@@ -1519,6 +1633,10 @@ impl LapceEditorBufferData {
                 } else {
                     let completion = Arc::make_mut(&mut self.completion);
                     completion.next();
+                    completion.resolve_current(
+                        self.proxy.clone(),
+                        ctx.get_external_handle(),
+                    );
                 }
             }
             ListPrevious => {
@@ -1534,7 +1652,116 @@ impl LapceEditorBufferData {
                 } else {
                     let completion = Arc::make_mut(&mut self.completion);
                     completion.previous();
+                    completion.resolve_current(
+                        self.proxy.clone(),
+                        ctx.get_external_handle(),
+                    );
+                }
+            }
+            ListFirst => {
+                if !self.has_completions() {
+                    return CommandExecuted::No;
+                }
+                let completion = Arc::make_mut(&mut self.completion);
+                completion.first();
+                completion
+                    .resolve_current(self.proxy.clone(), ctx.get_external_handle());
+            }
+            ListLast => {
+                if !self.has_completions() {
+                    return CommandExecuted::No;
+                }
+                let completion = Arc::make_mut(&mut self.completion);
+                completion.last();
+                completion
+                    .resolve_current(self.proxy.clone(), ctx.get_external_handle());
+            }
+            // The completion commands are left to the other keymaps of
+            // their key when there's no popup
+            CompletionAccept => {
+                if !self.has_completions() {
+                    return CommandExecuted::No;
+                }
+                self.accept_completion(ctx);
+            }
+            CompletionCancel => {
+                if !self.has_completions() {
+                    return CommandExecuted::No;
+                }
+                self.cancel_completion();
+            }
+            GetCompletion => {
+                if self.get_mode() != Mode::Insert {
+                    return CommandExecuted::No;
                 }
+                self.update_completion(ctx, true);
+            }
+            CompletionToggleDocumentation => {
+                if !self.has_completions()
+                    || self.completion.current_documentation().is_none()
+                {
+                    return CommandExecuted::No;
+                }
+                Arc::make_mut(&mut self.completion).toggle_documentation();
+            }
+            CompletionScrollDocumentationUp | CompletionScrollDocumentationDown => {
+                if !self.has_completions()
+                    || self.completion.current_documentation().is_none()
+                {
+                    return CommandExecuted::No;
+                }
+                // Three lines at a time. The documentation popup keeps it
+                // within its content, which only it knows the height of.
+                let delta = self.config.editor.line_height as f64 * 3.0;
+                let delta = if *cmd == CompletionScrollDocumentationUp {
+                    -delta
+                } else {
+                    delta
+                };
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ScrollCompletionDocumentation(delta),
+                    Target::Widget(self.completion.documentation_id),
+                ));
+            }
+            CompletionDebugDump => {
+                log::info!("{}", self.completion.debug_dump());
+            }
+            // Hidden kinds stay hidden for the sessions to come, so these
+            // work without a popup as well, like from the palette
+            CompletionToggleSnippets | CompletionToggleKeywords => {
+                let kind = match cmd {
+                    CompletionToggleSnippets => CompletionItemKind::Snippet,
+                    _ => CompletionItemKind::Keyword,
+                };
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ToggleCompletionKind(kind),
+                    Target::Widget(self.completion.id),
+                ));
+            }
+            CompletionNext
+            | CompletionPrevious
+            | CompletionPageNext
+            | CompletionPagePrevious
+            | CompletionFirst
+            | CompletionLast => {
+                if !self.has_completions() {
+                    return CommandExecuted::No;
+                }
+                let line_height = self.config.editor.line_height as f64;
+                let completion = Arc::make_mut(&mut self.completion);
+                match cmd {
+                    CompletionNext => completion.next(),
+                    CompletionPrevious => completion.previous(),
+                    CompletionPageNext => completion.page_next(line_height),
+                    CompletionPagePrevious => completion.page_previous(line_height),
+                    CompletionFirst => completion.first(),
+                    CompletionLast => completion.last(),
+                    _ => {}
+                }
+                completion
+                    .resolve_current(self.proxy.clone(), ctx.get_external_handle());
             }
             JumpToNextSnippetPlaceholder => {
                 if let Some(snippet) = self.editor.snippet.as_ref() {
@@ -1594,10 +1821,30 @@ impl LapceEditorBufferData {
                 }
             }
             PageUp => {
-                self.page_move(ctx, false, mods);
+                if self.has_completions() {
+                    let line_height = self.config.editor.line_height as f64;
+                    let completion = Arc::make_mut(&mut self.completion);
+                    completion.page_previous(line_height);
+                    completion.resolve_current(
+                        self.proxy.clone(),
+                        ctx.get_external_handle(),
+                    );
+                } else {
+                    self.page_move(ctx, false, mods);
+                }
             }
             PageDown => {
-                self.page_move(ctx, true, mods);
+                if self.has_completions() {
+                    let line_height = self.config.editor.line_height as f64;
+                    let completion = Arc::make_mut(&mut self.completion);
+                    completion.page_next(line_height);
+                    completion.resolve_current(
+                        self.proxy.clone(),
+                        ctx.get_external_handle(),
+                    );
+                } else {
+                    self.page_move(ctx, true, mods);
+                }
             }
             ScrollUp => {
                 self.scroll(ctx, false, count.unwrap_or(1), mods);
@@ -1906,14 +2153,22 @@ impl KeyPressFocus for LapceEditorBufferData {
 
     fn receive_char(&mut self, ctx: &mut EventCtx, c: &str) {
         if self.get_mode() == Mode::Insert {
+            if self.has_completions() && self.completion.is_commit_character(c) {
+                self.accept_completion_then(ctx, Some(c));
+                return;
+            }
+
             self.initiate_diagnostics_offset();
             let doc = Arc::make_mut(&mut self.doc);
             let cursor = &mut Arc::make_mut(&mut self.editor).new_cursor;
             let deltas = doc.do_insert(cursor, c);
 
-            self.update_completion(ctx);
+            self.update_completion(ctx, false);
             self.cancel_hover();
             self.apply_deltas(&deltas);
+            if self.completion.auto_accept_pending() {
+                self.accept_completion(ctx);
+            }
         } else if let Some(direction) = self.editor.inline_find.clone() {
             self.inline_find(ctx, direction.clone(), c);
             let editor = Arc::make_mut(&mut self.editor);
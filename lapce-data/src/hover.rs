@@ -169,7 +169,7 @@ impl Default for HoverData {
     }
 }
 
-fn parse_hover_markdown(
+pub(crate) fn parse_hover_markdown(
     syntax: Option<&Syntax>,
     text: &str,
     config: &EditorConfig,
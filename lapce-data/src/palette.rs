@@ -11,7 +11,6 @@ use lapce_core::mode::Mode;
 use lapce_core::movement::Movement;
 use lsp_types::{DocumentSymbolResponse, Range, SymbolKind};
 use serde_json;
-use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -1044,8 +1043,7 @@ impl PaletteViewData {
                 }
             })
             .collect();
-        items
-            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Less));
+        items.sort_by(|a, b| b.score.cmp(&a.score));
         items
     }
 }
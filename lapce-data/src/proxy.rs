@@ -22,6 +22,7 @@ use lapce_rpc::terminal::TermId;
 use lapce_rpc::RpcHandler;
 use lapce_rpc::{stdio_transport, Callback};
 use lapce_rpc::{ControlFlow, Handler};
+use lsp_types::CompletionContext;
 use lsp_types::CompletionItem;
 use lsp_types::Position;
 use lsp_types::Url;
@@ -176,6 +177,18 @@ impl Handler for LapceProxy {
                 );
             }
             ListDir { .. } | DiffFiles { .. } => {}
+            CompletionTriggerCharacters {
+                buffer_id,
+                characters,
+            } => {
+                let _ = self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::UpdateCompletionTriggerCharacters(
+                        buffer_id, characters,
+                    ),
+                    Target::Widget(self.tab_id),
+                );
+            }
             FileChange { event } => {
                 let _ = self.event_sink.submit_command(
                     LAPCE_UI_COMMAND,
@@ -479,6 +492,7 @@ impl LapceProxy {
         request_id: usize,
         buffer_id: BufferId,
         position: Position,
+        context: Option<CompletionContext>,
         f: Box<dyn Callback>,
     ) {
         self.rpc.send_rpc_request_async(
@@ -487,6 +501,7 @@ impl LapceProxy {
                 "request_id": request_id,
                 "buffer_id": buffer_id,
                 "position": position,
+                "context": context,
             }),
             f,
         );
@@ -440,12 +440,13 @@ impl Dispatcher {
                 buffer_id,
                 position,
                 request_id,
+                context,
             } => {
                 let buffers = self.buffers.lock();
                 let buffer = buffers.get(&buffer_id).unwrap();
                 self.lsp
                     .lock()
-                    .get_completion(id, request_id, buffer, position);
+                    .get_completion(id, request_id, buffer, position, context);
             }
             CompletionResolve {
                 buffer_id,
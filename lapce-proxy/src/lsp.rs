@@ -182,22 +182,33 @@ impl LspCatalog {
         _request_id: usize,
         buffer: &Buffer,
         position: Position,
+        context: Option<CompletionContext>,
     ) {
         if let Some(client) = self.clients.get(&buffer.language_id) {
             let uri = client.get_uri(buffer);
-            client.request_completion(uri, position, move |lsp_client, result| {
-                let mut resp = json!({ "id": id });
-                match result {
-                    Ok(v) => resp["result"] = v,
-                    Err(e) => {
-                        resp["error"] = json!({
-                            "code": 0,
-                            "message": format!("{}",e),
-                        })
+            client.request_completion(
+                uri,
+                position,
+                context,
+                move |lsp_client, result| {
+                    let mut resp = json!({ "id": id });
+                    match result {
+                        Ok(v) => {
+                            resp["result"] = json!({
+                                "source": lsp_client.name(),
+                                "response": v,
+                            })
+                        }
+                        Err(e) => {
+                            resp["error"] = json!({
+                                "code": 0,
+                                "message": format!("{}",e),
+                            })
+                        }
                     }
-                }
-                let _ = lsp_client.dispatcher.sender.send(resp);
-            });
+                    let _ = lsp_client.dispatcher.sender.send(resp);
+                },
+            );
         }
     }
 
@@ -447,6 +458,14 @@ impl LspClient {
         let _ = self.state.lock().process.kill();
     }
 
+    /// The name of the server, from its executable
+    pub fn name(&self) -> String {
+        std::path::Path::new(&self.exec_path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.exec_path.clone())
+    }
+
     pub fn get_uri(&self, buffer: &Buffer) -> Url {
         let exists = {
             let state = self.state.lock();
@@ -616,6 +635,24 @@ impl LspClient {
             serde_json::to_value(text_document_did_open_params).unwrap(),
         );
         self.send_notification("textDocument/didOpen", params);
+
+        let trigger_characters = self
+            .state
+            .lock()
+            .server_capabilities
+            .as_ref()
+            .and_then(|c| c.completion_provider.as_ref())
+            .and_then(|p| p.trigger_characters.clone())
+            .unwrap_or_default();
+        if !trigger_characters.is_empty() {
+            self.dispatcher.send_notification(
+                "completion_trigger_characters",
+                json!({
+                    "buffer_id": buffer_id,
+                    "characters": trigger_characters,
+                }),
+            );
+        }
     }
 
     pub fn send_did_save(&self, uri: Url) {
@@ -640,6 +677,14 @@ impl LspClient {
                 completion: Some(CompletionClientCapabilities {
                     completion_item: Some(CompletionItemCapability {
                         snippet_support: Some(true),
+                        label_details_support: Some(true),
+                        insert_replace_support: Some(true),
+                        insert_text_mode_support: Some(InsertTextModeSupport {
+                            value_set: vec![
+                                InsertTextMode::AsIs,
+                                InsertTextMode::AdjustIndentation,
+                            ],
+                        }),
                         resolve_support: Some(
                             CompletionItemCapabilityResolveSupport {
                                 properties: vec!["additionalTextEdits".to_string()],
@@ -835,6 +880,7 @@ impl LspClient {
         &self,
         document_uri: Url,
         position: Position,
+        context: Option<CompletionContext>,
         on_completion: CB,
     ) where
         CB: 'static + Send + FnOnce(&LspClient, Result<Value>),
@@ -846,7 +892,7 @@ impl LspClient {
             },
             work_done_progress_params: WorkDoneProgressParams::default(),
             partial_result_params: PartialResultParams::default(),
-            context: None,
+            context,
         };
         let params = Params::from(serde_json::to_value(completion_params).unwrap());
         self.send_request(
@@ -59,6 +59,10 @@ pub enum CoreNotification {
     CloseTerminal {
         term_id: TermId,
     },
+    CompletionTriggerCharacters {
+        buffer_id: BufferId,
+        characters: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, path::PathBuf};
 
-use lsp_types::{CompletionItem, Position};
+use lsp_types::{CompletionContext, CompletionItem, CompletionResponse, Position};
 use serde::{Deserialize, Serialize};
 use xi_rope::RopeDelta;
 
@@ -64,6 +64,7 @@ pub enum ProxyRequest {
         request_id: usize,
         buffer_id: BufferId,
         position: Position,
+        context: Option<CompletionContext>,
     },
     GlobalSearch {
         pattern: String,
@@ -122,3 +123,10 @@ pub enum ProxyRequest {
 pub struct ReadDirResponse {
     pub items: HashMap<PathBuf, FileNodeItem>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetCompletionResponse {
+    /// The name of the language server the items came from
+    pub source: String,
+    pub response: CompletionResponse,
+}
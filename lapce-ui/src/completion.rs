@@ -1,180 +1,31 @@
-use std::{cmp::Ordering, fmt::Display, sync::Arc};
+use std::{sync::Arc, time::Instant};
 
-use anyhow::Error;
 use druid::{
-    piet::{Text, TextAttribute, TextLayoutBuilder},
-    BoxConstraints, Command, Data, Env, Event, EventCtx, FontFamily, FontWeight,
-    LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect, RenderContext, Size,
-    Target, UpdateCtx, Widget, WidgetId, WidgetPod,
+    piet::{PietTextLayout, Text, TextAttribute, TextLayout, TextLayoutBuilder},
+    theme, ArcStr, BoxConstraints, Color, Command, Data, Env, Event, EventCtx,
+    FontDescriptor, FontFamily, FontWeight, LayoutCtx, LifeCycle, LifeCycleCtx,
+    PaintCtx, Point, Rect, RenderContext, Size, Target, UpdateCtx, Widget, WidgetId,
+    WidgetPod,
 };
-use itertools::Itertools;
+use lapce_core::command::FocusCommand;
 use lapce_data::{
-    command::{LapceUICommand, LAPCE_UI_COMMAND},
-    completion::{CompletionData, CompletionStatus, ScoredCompletionItem},
-    config::LapceTheme,
+    command::{
+        CommandKind, LapceCommand, LapceUICommand, LAPCE_COMMAND, LAPCE_UI_COMMAND,
+    },
+    completion::{
+        coalesce_ranges, kind_tag, parse_documentation, CompletionData,
+        CompletionDisplayMode, CompletionStatus, INLINE_SEPARATOR,
+    },
+    config::{Config, LapceTheme},
     data::LapceTabData,
+    rich_text::RichText,
 };
-use lsp_types::CompletionItem;
-use regex::Regex;
-use std::str::FromStr;
 
 use crate::{
     scroll::{LapceIdentityWrapper, LapceScrollNew},
-    svg::completion_svg,
+    svg::{completion_kind_color, completion_svg},
 };
 
-#[derive(Debug)]
-struct Snippet {
-    elements: Vec<SnippetElement>,
-}
-
-impl Snippet {
-    fn extract_elements(
-        s: &str,
-        pos: usize,
-        escs: Vec<&str>,
-        loose_escs: Vec<&str>,
-    ) -> (Vec<SnippetElement>, usize) {
-        let mut elements = Vec::new();
-        let mut pos = pos;
-        loop {
-            if s.len() == pos {
-                break;
-            } else if let Some((ele, end)) = Self::extract_tabstop(s, pos) {
-                elements.push(ele);
-                pos = end;
-            } else if let Some((ele, end)) = Self::extract_placeholder(s, pos) {
-                elements.push(ele);
-                pos = end;
-            } else if let Some((ele, end)) =
-                Self::extract_text(s, pos, escs.clone(), loose_escs.clone())
-            {
-                elements.push(ele);
-                pos = end;
-            } else {
-                break;
-            }
-        }
-        (elements, pos)
-    }
-
-    fn extract_tabstop(s: &str, pos: usize) -> Option<(SnippetElement, usize)> {
-        for re in &[
-            Regex::new(r#"^\$(\d+)"#).unwrap(),
-            Regex::new(r#"^\$\{(\d+)\}"#).unwrap(),
-        ] {
-            if let Some(caps) = re.captures(&s[pos..]) {
-                let end = pos + re.find(&s[pos..])?.end();
-                let m = caps.get(1)?;
-                let n = m.as_str().parse::<usize>().ok()?;
-                return Some((SnippetElement::Tabstop(n), end));
-            }
-        }
-
-        None
-    }
-
-    fn extract_placeholder(s: &str, pos: usize) -> Option<(SnippetElement, usize)> {
-        let re = Regex::new(r#"^\$\{(\d+):(.*?)\}"#).unwrap();
-        let end = pos + re.find(&s[pos..])?.end();
-
-        let caps = re.captures(&s[pos..])?;
-
-        let tab = caps.get(1)?.as_str().parse::<usize>().ok()?;
-
-        let m = caps.get(2)?;
-        let content = m.as_str();
-        if content.is_empty() {
-            return Some((
-                SnippetElement::PlaceHolder(
-                    tab,
-                    vec![SnippetElement::Text("".to_string())],
-                ),
-                end,
-            ));
-        }
-        let (els, pos) =
-            Self::extract_elements(s, pos + m.start(), vec!["$", "}", "\\"], vec![]);
-        Some((SnippetElement::PlaceHolder(tab, els), pos + 1))
-    }
-
-    fn extract_text(
-        s: &str,
-        pos: usize,
-        escs: Vec<&str>,
-        loose_escs: Vec<&str>,
-    ) -> Option<(SnippetElement, usize)> {
-        let mut s = &s[pos..];
-        let mut ele = "".to_string();
-        let mut end = pos;
-
-        while !s.is_empty() {
-            if s.len() >= 2 {
-                let esc = &s[..2];
-                let mut new_escs = escs.clone();
-                new_escs.extend_from_slice(&loose_escs);
-
-                if new_escs
-                    .iter()
-                    .map(|e| format!("\\{}", e))
-                    .any(|x| x == *esc)
-                {
-                    ele = ele + &s[1..2].to_string();
-                    end += 2;
-                    s = &s[2..];
-                    continue;
-                }
-            }
-            if escs.contains(&&s[0..1]) {
-                break;
-            }
-            ele = ele + &s[0..1].to_string();
-            end += 1;
-            s = &s[1..];
-        }
-        if ele.is_empty() {
-            return None;
-        }
-        Some((SnippetElement::Text(ele), end))
-    }
-}
-
-impl FromStr for Snippet {
-    type Err = Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (elements, _) = Self::extract_elements(s, 0, vec!["$", "\\"], vec!["}"]);
-        Ok(Snippet { elements })
-    }
-}
-
-impl Display for Snippet {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let text = self.elements.iter().map(|e| e.to_string()).join("");
-        f.write_str(&text)
-    }
-}
-
-#[derive(Debug)]
-enum SnippetElement {
-    Text(String),
-    PlaceHolder(usize, Vec<SnippetElement>),
-    Tabstop(usize),
-}
-
-impl Display for SnippetElement {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self {
-            SnippetElement::Text(text) => f.write_str(text),
-            SnippetElement::PlaceHolder(tab, elements) => {
-                let elements = elements.iter().map(|e| e.to_string()).join("");
-                write!(f, "${{{}:{}}}", tab, elements)
-            }
-            SnippetElement::Tabstop(tab) => write!(f, "${}", tab),
-        }
-    }
-}
-
 pub struct CompletionContainer {
     id: WidgetId,
     scroll_id: WidgetId,
@@ -183,6 +34,9 @@ pub struct CompletionContainer {
         LapceIdentityWrapper<LapceScrollNew<LapceTabData, CompletionNew>>,
     >,
     content_size: Size,
+    /// The session last shown, painted while the popup fades out, since
+    /// cancelling already cleared its items
+    fading_out: Option<Arc<CompletionData>>,
 }
 
 impl CompletionContainer {
@@ -196,6 +50,7 @@ impl CompletionContainer {
             completion: WidgetPod::new(completion),
             scroll_id: data.scroll_id,
             content_size: Size::ZERO,
+            fading_out: None,
         }
     }
 
@@ -205,14 +60,10 @@ impl CompletionContainer {
         data: &LapceTabData,
         env: &Env,
     ) {
-        let width = ctx.size().width;
+        let size = ctx.size();
         let line_height = data.config.editor.line_height as f64;
-        let rect = Size::new(width, line_height)
-            .to_rect()
-            .with_origin(Point::new(
-                0.0,
-                data.completion.index as f64 * line_height,
-            ));
+        let (y0, y1) = data.completion.selection_span(line_height, size.height);
+        let rect = Rect::new(0.0, y0, size.width, y1);
         if self
             .completion
             .widget_mut()
@@ -244,13 +95,76 @@ impl Widget<LapceTabData> for CompletionContainer {
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
                 match command {
-                    LapceUICommand::UpdateCompletion(request_id, input, resp) => {
+                    LapceUICommand::UpdateCompletion(
+                        request_id,
+                        input,
+                        resp,
+                        source,
+                    ) => {
+                        // The cursor was moved away while the request was in
+                        // flight, so the response would pop up in the wrong place
+                        let is_at_cursor = data
+                            .main_split
+                            .active_editor()
+                            .map(|editor| {
+                                let doc = data.main_split.editor_doc(editor.view_id);
+                                data.completion
+                                    .is_at(doc.id(), editor.new_cursor.offset())
+                            })
+                            .unwrap_or(false);
                         let completion = Arc::make_mut(&mut data.completion);
-                        completion.receive(
+                        if is_at_cursor {
+                            completion.receive(
+                                *request_id,
+                                input.to_owned(),
+                                resp.to_owned(),
+                                source.to_owned(),
+                            );
+                            if completion.is_filter_pending() {
+                                completion.filter_in_background(
+                                    completion.id,
+                                    ctx.get_external_handle(),
+                                );
+                            }
+                            completion.resolve_current(
+                                data.proxy.clone(),
+                                ctx.get_external_handle(),
+                            );
+                        } else {
+                            completion.cancel();
+                        }
+                    }
+                    LapceUICommand::UpdateCompletionItem(
+                        request_id,
+                        index,
+                        item,
+                    ) => {
+                        let completion = Arc::make_mut(&mut data.completion);
+                        completion.receive_resolve(
+                            *request_id,
+                            *index,
+                            item.as_ref().clone(),
+                        );
+                    }
+                    LapceUICommand::UpdateFilteredCompletionItems(
+                        request_id,
+                        input,
+                        items,
+                    ) => {
+                        let completion = Arc::make_mut(&mut data.completion);
+                        completion.receive_filtered(
                             *request_id,
-                            input.to_owned(),
-                            resp.to_owned(),
+                            input,
+                            items.to_owned(),
                         );
+                        completion.resolve_current(
+                            data.proxy.clone(),
+                            ctx.get_external_handle(),
+                        );
+                    }
+                    LapceUICommand::ToggleCompletionKind(kind) => {
+                        let completion = Arc::make_mut(&mut data.completion);
+                        completion.toggle_kind(*kind);
                     }
                     LapceUICommand::CancelCompletion(request_id) => {
                         if data.completion.request_id == *request_id {
@@ -261,6 +175,17 @@ impl Widget<LapceTabData> for CompletionContainer {
                     _ => {}
                 }
             }
+            Event::AnimFrame(_) => {
+                if data.completion.is_fading() {
+                    let completion = Arc::make_mut(&mut data.completion);
+                    if completion.animate(Instant::now()) {
+                        ctx.request_anim_frame();
+                    }
+                }
+                if !data.completion.is_fading_out() {
+                    self.fading_out = None;
+                }
+            }
             _ => {}
         }
         self.completion.event(ctx, event, data, env);
@@ -304,21 +229,20 @@ impl Widget<LapceTabData> for CompletionContainer {
             }
         }
 
-        if old_data.completion.input != data.completion.input
-            || old_data.completion.request_id != data.completion.request_id
-            || old_data.completion.status != data.completion.status
-            || !old_data
-                .completion
-                .current_items()
-                .same(data.completion.current_items())
-            || !old_data
-                .completion
-                .filtered_items
-                .same(&data.completion.filtered_items)
-        {
+        if data.completion.should_repaint(&old_data.completion) {
             ctx.request_layout();
         }
 
+        if old_completion.is_visible() != completion.is_visible() {
+            if !completion.is_visible() {
+                self.fading_out = Some(old_completion.clone());
+            }
+            ctx.request_anim_frame();
+        }
+        if old_completion.alpha() != completion.alpha() {
+            ctx.request_paint();
+        }
+
         if (old_completion.status == CompletionStatus::Inactive
             && completion.status != CompletionStatus::Inactive)
             || (old_completion.input != completion.input)
@@ -330,20 +254,42 @@ impl Widget<LapceTabData> for CompletionContainer {
             ));
         }
 
+        if completion.is_visible()
+            && (old_completion.index != completion.index
+                || !old_completion.is_visible())
+        {
+            completion.announce_selection();
+        }
+
         if old_completion.index != completion.index {
-            self.ensure_item_visble(ctx, data, env);
+            if completion.display_mode == CompletionDisplayMode::Inline {
+                // Moving to another page changes the width of the row
+                if old_completion.inline_range() != completion.inline_range() {
+                    ctx.request_layout();
+                }
+            } else {
+                self.ensure_item_visble(ctx, data, env);
+            }
             ctx.request_paint();
         }
+
+        self.completion.update(ctx, data, env);
     }
 
     fn layout(
         &mut self,
         ctx: &mut LayoutCtx,
-        _bc: &BoxConstraints,
+        bc: &BoxConstraints,
         data: &LapceTabData,
         env: &Env,
     ) -> Size {
-        let size = data.completion.size;
+        let line_height = data.config.editor.line_height as f64;
+        let char_width = data.config.editor_char_width(ctx.text());
+        let size = data.completion.popup_size(char_width, line_height);
+        let size = Size::new(
+            size.width.min(bc.max().width),
+            size.height.min(bc.max().height),
+        );
         let bc = BoxConstraints::new(Size::ZERO, size);
         self.content_size = self.completion.layout(ctx, &bc, data, env);
         self.completion.set_origin(ctx, data, env, Point::ZERO);
@@ -352,27 +298,93 @@ impl Widget<LapceTabData> for CompletionContainer {
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
-        if data.completion.status != CompletionStatus::Inactive
-            && data.completion.len() > 0
-        {
-            let shadow_width = 5.0;
+        let alpha = data.completion.alpha();
+        // While fading out, the session that was last shown is painted
+        let completion = match self.fading_out.as_ref() {
+            Some(completion) if data.completion.is_fading_out() => completion,
+            _ => &data.completion,
+        };
+        let config = &data.config;
+        if completion.is_visible() {
+            let shadow_width = config.editor.completion_shadow_width as f64;
             let rect = self.content_size.to_rect();
-            ctx.blurred_rect(
-                rect,
-                shadow_width,
-                data.config
-                    .get_color_unchecked(LapceTheme::LAPCE_DROPDOWN_SHADOW),
-            );
+            if shadow_width > 0.0 {
+                ctx.blurred_rect(
+                    rect,
+                    shadow_width,
+                    &theme_color(config, LapceTheme::COMPLETION_SHADOW, alpha),
+                );
+            }
             self.completion.paint(ctx, data, env);
+
+            if completion.display_mode == CompletionDisplayMode::Popup {
+                if let Some(position) = completion.position() {
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(position)
+                        .font(
+                            config.editor.font_family(),
+                            config.editor.font_size as f64 - 2.0,
+                        )
+                        .text_color(theme_color(
+                            config,
+                            LapceTheme::EDITOR_DIM,
+                            alpha,
+                        ))
+                        .build()
+                        .unwrap();
+                    // In the bottom right corner, over whatever row is there
+                    let size = text_layout.size();
+                    let padding = 3.0;
+                    let origin = Point::new(
+                        rect.x1 - size.width - padding,
+                        rect.y1 - size.height - padding,
+                    );
+                    ctx.fill(
+                        Rect::from_origin_size(origin, size)
+                            .inflate(padding, padding / 2.0),
+                        &theme_color(
+                            config,
+                            LapceTheme::COMPLETION_BACKGROUND,
+                            alpha,
+                        ),
+                    );
+                    ctx.draw_text(&text_layout, origin);
+                }
+            }
+
+            let border_width = config.editor.completion_border_width as f64;
+            if border_width > 0.0 {
+                ctx.stroke(
+                    rect.inset(-border_width / 2.0),
+                    &theme_color(config, LapceTheme::COMPLETION_BORDER, alpha),
+                    border_width,
+                );
+            }
         }
     }
 }
 
-pub struct CompletionNew {}
+/// `color` with its opacity scaled by `alpha`. Piet can't paint a group
+/// of shapes with an opacity, so each color is faded as it's drawn.
+fn faded(color: &Color, alpha: f64) -> Color {
+    let (r, g, b, a) = color.as_rgba();
+    Color::rgba(r, g, b, a * alpha)
+}
+
+/// The theme color `name`, faded by `alpha`
+fn theme_color(config: &Config, name: &str, alpha: f64) -> Color {
+    faded(config.get_color_unchecked(name), alpha)
+}
+
+pub struct CompletionNew {
+    /// The session last shown, painted while the popup fades out
+    fading_out: Option<Arc<CompletionData>>,
+}
 
 impl CompletionNew {
     pub fn new() -> Self {
-        Self {}
+        Self { fading_out: None }
     }
 }
 
@@ -382,14 +394,136 @@ impl Default for CompletionNew {
     }
 }
 
+impl CompletionNew {
+    /// Paint the page of items the selected one is on as a single row,
+    /// with the selected label highlighted
+    fn paint_inline(
+        &self,
+        ctx: &mut PaintCtx,
+        completion: &CompletionData,
+        config: &Config,
+        alpha: f64,
+    ) {
+        let line_height = config.editor.line_height as f64;
+        let range = completion.inline_range();
+        let font_family =
+            FontFamily::new_unchecked(config.editor.font_family.clone());
+        let font_size = config.editor.font_size as f64;
+        let match_color =
+            &theme_color(config, LapceTheme::COMPLETION_MATCH_COLOR, alpha);
+        let y = text_y_shift(ctx, config);
+
+        if completion.shows_no_matches() {
+            let text_layout = no_matches_layout(ctx, config, alpha);
+            ctx.draw_text(&text_layout, Point::new(5.0, y));
+            return;
+        }
+
+        let mut x = 5.0;
+        let rows = completion.rows().skip(range.start).take(range.len());
+        for (n, item) in rows.enumerate() {
+            if n > 0 {
+                let separator = ctx
+                    .text()
+                    .new_text_layout(INLINE_SEPARATOR)
+                    .font(font_family.clone(), font_size)
+                    .text_color(theme_color(
+                        config,
+                        LapceTheme::COMPLETION_DETAIL,
+                        alpha,
+                    ))
+                    .build()
+                    .unwrap();
+                ctx.draw_text(&separator, Point::new(x, y));
+                x += separator.size().width;
+            }
+
+            let mut text_layout = ctx
+                .text()
+                .new_text_layout(item.label.to_string())
+                .font(font_family.clone(), font_size)
+                .text_color(theme_color(
+                    config,
+                    LapceTheme::EDITOR_FOREGROUND,
+                    alpha,
+                ));
+            if completion.highlight_matches {
+                for range in coalesce_ranges(item.indices) {
+                    text_layout = text_layout
+                        .range_attribute(
+                            range.clone(),
+                            TextAttribute::TextColor(match_color.clone()),
+                        )
+                        .range_attribute(
+                            range,
+                            TextAttribute::Weight(FontWeight::BOLD),
+                        );
+                }
+            }
+            let text_layout = text_layout.build().unwrap();
+            let width = text_layout.size().width;
+
+            if item.selected {
+                ctx.fill(
+                    Rect::new(x - 2.0, 0.0, x + width + 2.0, line_height),
+                    &theme_color(config, LapceTheme::COMPLETION_CURRENT, alpha),
+                );
+            }
+            ctx.draw_text(&text_layout, Point::new(x, y));
+            x += width;
+        }
+    }
+}
+
 impl Widget<LapceTabData> for CompletionNew {
     fn event(
         &mut self,
-        _ctx: &mut EventCtx,
-        _event: &Event,
-        _data: &mut LapceTabData,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
         _env: &Env,
     ) {
+        if !data.completion.is_visible() {
+            return;
+        }
+        let line_height = data.config.editor.line_height as f64;
+        match event {
+            // The wheel moves the selection, and the list scrolls along
+            Event::Wheel(mouse) => {
+                let completion = Arc::make_mut(&mut data.completion);
+                if mouse.wheel_delta.y > 0.0 {
+                    completion.next();
+                } else if mouse.wheel_delta.y < 0.0 {
+                    completion.previous();
+                }
+                completion
+                    .resolve_current(data.proxy.clone(), ctx.get_external_handle());
+                ctx.set_handled();
+            }
+            // A left click accepts the item under it, like in other editors
+            Event::MouseDown(mouse) => {
+                if !mouse.button.is_left()
+                    || data.completion.display_mode == CompletionDisplayMode::Inline
+                {
+                    return;
+                }
+                let row = match data.completion.row_at(mouse.pos.y, line_height) {
+                    Some(row) => row,
+                    None => return,
+                };
+                Arc::make_mut(&mut data.completion).select(row);
+                ctx.submit_command(Command::new(
+                    LAPCE_COMMAND,
+                    LapceCommand {
+                        kind: CommandKind::Focus(FocusCommand::CompletionAccept),
+                        data: None,
+                    },
+                    Target::Widget(data.focus),
+                ));
+                ctx.set_handled();
+            }
+            _ => {}
+        }
     }
 
     fn lifecycle(
@@ -404,10 +538,15 @@ impl Widget<LapceTabData> for CompletionNew {
     fn update(
         &mut self,
         _ctx: &mut UpdateCtx,
-        _old_data: &LapceTabData,
-        _data: &LapceTabData,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
         _env: &Env,
     ) {
+        if old_data.completion.is_visible() && !data.completion.is_visible() {
+            self.fading_out = Some(old_data.completion.clone());
+        } else if !data.completion.is_fading_out() {
+            self.fading_out = None;
+        }
     }
 
     fn layout(
@@ -418,182 +557,660 @@ impl Widget<LapceTabData> for CompletionNew {
         _env: &Env,
     ) -> Size {
         let line_height = data.config.editor.line_height as f64;
-        let height = data.completion.len();
+        if data.completion.display_mode == CompletionDisplayMode::Inline {
+            return Size::new(bc.max().width, line_height);
+        }
+        let mut height = data.completion.len();
+        if data.completion.more_label().is_some()
+            || data.completion.shows_no_matches()
+        {
+            // The row telling how many items were left out, or that none match
+            height += 1;
+        }
         let height = height as f64 * line_height;
         Size::new(bc.max().width, height)
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
-        if data.completion.status == CompletionStatus::Inactive {
+        let alpha = data.completion.alpha();
+        let completion = match self.fading_out.as_ref() {
+            Some(completion) if data.completion.is_fading_out() => completion,
+            _ => &data.completion,
+        };
+        if !completion.is_visible() {
             return;
         }
-        let line_height = data.config.editor.line_height as f64;
+        let config = &data.config;
+        let line_height = config.editor.line_height as f64;
         let rect = ctx.region().bounding_box();
         let size = ctx.size();
 
-        let _input = &data.completion.input;
-        let items: &Vec<ScoredCompletionItem> = data.completion.current_items();
-
         ctx.fill(
             rect,
-            data.config
-                .get_color_unchecked(LapceTheme::COMPLETION_BACKGROUND),
+            &theme_color(config, LapceTheme::COMPLETION_BACKGROUND, alpha),
         );
 
+        if completion.display_mode == CompletionDisplayMode::Inline {
+            self.paint_inline(ctx, completion, config, alpha);
+            return;
+        }
+
         let start_line = (rect.y0 / line_height).floor() as usize;
         let end_line = (rect.y1 / line_height).ceil() as usize;
 
-        for line in start_line..end_line {
-            if line >= items.len() {
-                break;
-            }
+        // The icon column is kept even for items without a kind,
+        // so that all the labels line up, unless the popup is compact
+        let icon_width = completion.icon_width(line_height);
+        // A bar on the left edge of the selected row, so the selection is
+        // easy to spot even when its background barely stands out
+        let accent_width = 3.0;
+        let y_shift = text_y_shift(ctx, config);
 
-            if line == data.completion.index {
+        let rows = completion
+            .rows()
+            .enumerate()
+            .skip(start_line)
+            .take(end_line.saturating_sub(start_line));
+        for (line, item) in rows {
+            // The selection spans the whole row, including the padding
+            // and whatever part of the popup the region covers
+            let row_rect = Rect::new(
+                rect.x0.min(0.0),
+                line as f64 * line_height,
+                rect.x1.max(size.width),
+                (line + 1) as f64 * line_height,
+            );
+            if item.selected {
                 ctx.fill(
-                    Rect::ZERO
-                        .with_origin(Point::new(0.0, line as f64 * line_height))
-                        .with_size(Size::new(size.width, line_height)),
-                    data.config
-                        .get_color_unchecked(LapceTheme::COMPLETION_CURRENT),
+                    row_rect,
+                    &theme_color(config, LapceTheme::COMPLETION_CURRENT, alpha),
                 );
             }
 
-            let item = &items[line];
+            let y = line_height * line as f64 + y_shift;
 
-            let y = line_height * line as f64 + 5.0;
+            if icon_width == 0.0 {
+                // Compact, without the icon column
+            } else if let Some(color) = item.color.as_ref() {
+                let size = 12.0;
+                let rect = Size::new(size, size).to_rect().with_origin(Point::new(
+                    (icon_width - size) / 2.0,
+                    (line_height - size) / 2.0 + line_height * line as f64,
+                ));
+                ctx.fill(rect, &faded(color, alpha));
+                // So that colors close to the background still show
+                ctx.stroke(
+                    rect,
+                    &theme_color(config, LapceTheme::COMPLETION_DETAIL, alpha),
+                    1.0,
+                );
+            } else if !completion.show_kind_icons {
+                if let Some(kind) = item.kind {
+                    let color = completion_kind_color(kind, config);
+                    let rect = Size::new(icon_width, line_height)
+                        .to_rect()
+                        .with_origin(Point::new(0.0, line_height * line as f64));
+                    ctx.fill(rect, &color.clone().with_alpha(0.3 * alpha));
 
-            if let Some((svg, color)) = completion_svg(item.item.kind, &data.config)
-            {
-                let color = color.unwrap_or_else(|| {
-                    data.config
-                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
-                        .clone()
-                });
-                let rect = Size::new(line_height, line_height)
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(kind_tag(kind))
+                        .font(
+                            config.editor.font_family(),
+                            config.editor.font_size as f64 - 4.0,
+                        )
+                        .text_color(faded(&color, alpha))
+                        .build()
+                        .unwrap();
+                    let text_size = text_layout.size();
+                    ctx.draw_text(
+                        &text_layout,
+                        Point::new(
+                            (icon_width - text_size.width) / 2.0,
+                            (line_height - text_size.height) / 2.0
+                                + line_height * line as f64,
+                        ),
+                    );
+                }
+            } else if let Some(icon) = completion_svg(item.kind, config) {
+                let rect = Size::new(icon_width, line_height)
                     .to_rect()
                     .with_origin(Point::new(0.0, line_height * line as f64));
-                ctx.fill(rect, &color.clone().with_alpha(0.3));
+                ctx.fill(rect, &icon.color.clone().with_alpha(0.3 * alpha));
 
                 let width = 16.0;
                 let height = 16.0;
                 let rect =
                     Size::new(width, height).to_rect().with_origin(Point::new(
-                        (line_height - width) / 2.0,
+                        (icon_width - width) / 2.0,
                         (line_height - height) / 2.0 + line_height * line as f64,
                     ));
-                ctx.draw_svg(&svg, rect, Some(&color));
+                ctx.draw_svg(&icon.svg, rect, Some(&faded(&icon.color, alpha)));
+            }
+
+            if item.selected {
+                ctx.fill(
+                    Rect::new(0.0, row_rect.y0, accent_width, row_rect.y1),
+                    &theme_color(
+                        config,
+                        LapceTheme::COMPLETION_CURRENT_ACCENT,
+                        alpha,
+                    ),
+                );
             }
 
-            let focus_color =
-                data.config.get_color_unchecked(LapceTheme::EDITOR_FOCUS);
-            let content = item.item.label.as_str();
-            let point = Point::new(line_height + 5.0, y);
+            let match_color =
+                &theme_color(config, LapceTheme::COMPLETION_MATCH_COLOR, alpha);
+            let content = &item.label;
+            let point = Point::new(icon_width + 5.0, y);
 
+            let text_color = if item.deprecated {
+                LapceTheme::EDITOR_DIM
+            } else {
+                LapceTheme::EDITOR_FOREGROUND
+            };
+            // The label detail, like the signature of a function, is part of
+            // the same layout so that it's clipped and shifted with the label
+            let mut text = content.to_string();
+            if let Some(label_detail) = item.label_detail.as_ref() {
+                text.push_str(label_detail);
+            }
             let mut text_layout = ctx
                 .text()
-                .new_text_layout(content.to_string())
+                .new_text_layout(text)
                 .font(
-                    FontFamily::new_unchecked(
-                        data.config.editor.font_family.clone(),
-                    ),
-                    data.config.editor.font_size as f64,
+                    FontFamily::new_unchecked(config.editor.font_family.clone()),
+                    config.editor.font_size as f64,
                 )
-                .text_color(
-                    data.config
-                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
-                        .clone(),
-                );
-            for i in &item.indices {
-                let i = *i;
-                text_layout = text_layout.range_attribute(
-                    i..i + 1,
-                    TextAttribute::TextColor(focus_color.clone()),
-                );
+                .text_color(theme_color(config, text_color, alpha));
+            if item.deprecated {
+                text_layout = text_layout
+                    .default_attribute(TextAttribute::Strikethrough(true));
+            }
+            if let Some(label_detail) = item.label_detail.as_ref() {
                 text_layout = text_layout.range_attribute(
-                    i..i + 1,
-                    TextAttribute::Weight(FontWeight::BOLD),
+                    content.len()..content.len() + label_detail.len(),
+                    TextAttribute::TextColor(theme_color(
+                        config,
+                        LapceTheme::COMPLETION_DETAIL,
+                        alpha,
+                    )),
                 );
             }
+            if completion.highlight_matches {
+                for range in coalesce_ranges(item.indices) {
+                    text_layout = text_layout.range_attribute(
+                        range.clone(),
+                        TextAttribute::TextColor(match_color.clone()),
+                    );
+                    text_layout = text_layout.range_attribute(
+                        range,
+                        TextAttribute::Weight(FontWeight::BOLD),
+                    );
+                }
+            }
             let text_layout = text_layout.build().unwrap();
-            ctx.draw_text(&text_layout, point);
+
+            // Long labels are clipped to the popup, and on the selected row
+            // shifted so that the last matched character stays visible
+            let label_width = size.width - point.x - 5.0;
+            let mut shift = 0.0;
+            if item.selected {
+                if let Some(last) = item.indices.last() {
+                    let end = text_layout
+                        .hit_test_text_position((*last + 1).min(content.len()))
+                        .point
+                        .x;
+                    if end > label_width {
+                        shift = end - label_width;
+                    }
+                }
+            }
+            ctx.with_save(|ctx| {
+                ctx.clip(Rect::new(
+                    point.x,
+                    line_height * line as f64,
+                    point.x + label_width,
+                    line_height * (line + 1) as f64,
+                ));
+                ctx.draw_text(&text_layout, Point::new(point.x - shift, point.y));
+            });
+
+            // The source tag goes on the far right, and the detail left of it
+            let char_width = config.editor_char_width(ctx.text());
+            let mut right = size.width - 5.0;
+            if let Some(source) = item.source {
+                let source_layout = ctx
+                    .text()
+                    .new_text_layout(source.to_string())
+                    .font(
+                        FontFamily::new_unchecked(config.editor.font_family.clone()),
+                        config.editor.font_size as f64 - 2.0,
+                    )
+                    .text_color(theme_color(
+                        config,
+                        LapceTheme::COMPLETION_DETAIL,
+                        alpha,
+                    ))
+                    .build()
+                    .unwrap();
+                let source_size = source_layout.size();
+                right -= source_size.width;
+                ctx.draw_text(
+                    &source_layout,
+                    Point::new(
+                        right,
+                        line_height * line as f64
+                            + (line_height - source_size.height) / 2.0,
+                    ),
+                );
+                right -= char_width;
+            }
+
+            if let Some(detail) = item.detail.as_ref() {
+                let available =
+                    right - point.x - text_layout.size().width - char_width * 2.0;
+                let max_chars = (available / char_width).floor();
+                if max_chars >= 1.0 {
+                    let detail = truncate_with_ellipsis(detail, max_chars as usize);
+                    let detail_layout = ctx
+                        .text()
+                        .new_text_layout(detail)
+                        .font(
+                            FontFamily::new_unchecked(
+                                config.editor.font_family.clone(),
+                            ),
+                            config.editor.font_size as f64,
+                        )
+                        .text_color(theme_color(
+                            config,
+                            LapceTheme::COMPLETION_DETAIL,
+                            alpha,
+                        ))
+                        .build()
+                        .unwrap();
+                    let x = right - detail_layout.size().width;
+                    ctx.draw_text(&detail_layout, Point::new(x, y));
+                }
+            }
+        }
+
+        let len = completion.len();
+        if let Some(more) = completion
+            .more_label()
+            .filter(|_| (start_line..end_line).contains(&len))
+        {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(more)
+                .font(
+                    FontFamily::new_unchecked(config.editor.font_family.clone()),
+                    config.editor.font_size as f64,
+                )
+                .text_color(theme_color(
+                    config,
+                    LapceTheme::COMPLETION_DETAIL,
+                    alpha,
+                ))
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(icon_width + 5.0, line_height * len as f64 + y_shift),
+            );
+        }
+
+        if completion.shows_no_matches() {
+            let text_layout = no_matches_layout(ctx, config, alpha);
+            ctx.draw_text(&text_layout, Point::new(icon_width + 5.0, y_shift));
         }
     }
 }
 
-#[derive(Clone)]
-pub struct CompletionState {
-    pub widget_id: WidgetId,
-    pub items: Vec<ScoredCompletionItem>,
-    pub input: String,
-    pub offset: usize,
-    pub index: usize,
-    pub scroll_offset: f64,
+/// How far down a row its text starts, so that text in the editor font is
+/// centered in the `line_height` of the row, whatever the font size
+fn text_y_shift(ctx: &mut PaintCtx, config: &Config) -> f64 {
+    let text_layout = ctx
+        .text()
+        .new_text_layout("W")
+        .font(config.editor.font_family(), config.editor.font_size as f64)
+        .build()
+        .unwrap();
+    (config.editor.line_height as f64 - text_layout.size().height) / 2.0
+}
+
+/// The text of the row shown in place of the items when none match
+fn no_matches_layout(
+    ctx: &mut PaintCtx,
+    config: &Config,
+    alpha: f64,
+) -> PietTextLayout {
+    ctx.text()
+        .new_text_layout("No matches")
+        .font(
+            FontFamily::new_unchecked(config.editor.font_family.clone()),
+            config.editor.font_size as f64,
+        )
+        .text_color(theme_color(config, LapceTheme::COMPLETION_DETAIL, alpha))
+        .build()
+        .unwrap()
+}
+
+/// Cut `text` down to at most `max_chars` characters, replacing the
+/// last one with an ellipsis if anything was removed.
+fn truncate_with_ellipsis(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String =
+        text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// The popup showing the documentation of the selected completion item
+pub struct CompletionDocumentation {
+    id: WidgetId,
+    scroll_id: WidgetId,
+    documentation: WidgetPod<
+        LapceTabData,
+        LapceIdentityWrapper<
+            LapceScrollNew<LapceTabData, CompletionDocumentationContent>,
+        >,
+    >,
+    content_size: Size,
 }
 
-impl CompletionState {
-    pub fn new() -> CompletionState {
-        CompletionState {
-            widget_id: WidgetId::next(),
-            items: Vec::new(),
-            input: "".to_string(),
-            offset: 0,
-            index: 0,
-            scroll_offset: 0.0,
+impl CompletionDocumentation {
+    pub fn new(data: &CompletionData) -> Self {
+        let documentation = LapceIdentityWrapper::wrap(
+            LapceScrollNew::new(CompletionDocumentationContent::new()).vertical(),
+            data.documentation_scroll_id,
+        );
+        Self {
+            id: data.documentation_id,
+            scroll_id: data.documentation_scroll_id,
+            documentation: WidgetPod::new(documentation),
+            content_size: Size::ZERO,
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.items.iter().filter(|i| i.score != 0).count()
+    fn scroll_to_top(&mut self, ctx: &mut UpdateCtx, env: &Env) {
+        let rect = Size::new(ctx.size().width, 0.0).to_rect();
+        if self
+            .documentation
+            .widget_mut()
+            .inner_mut()
+            .scroll_to_visible(rect, env)
+        {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ResetFade,
+                Target::Widget(self.scroll_id),
+            ));
+        }
+    }
+}
+
+impl Widget<LapceTabData> for CompletionDocumentation {
+    fn id(&self) -> Option<WidgetId> {
+        Some(self.id)
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        if let Event::Command(cmd) = event {
+            if let Some(LapceUICommand::ScrollCompletionDocumentation(delta)) =
+                cmd.get(LAPCE_UI_COMMAND)
+            {
+                let scroll = self.documentation.widget().inner();
+                let max = scroll.child_size().height - self.content_size.height;
+                let completion = Arc::make_mut(&mut data.completion);
+                completion.scroll_documentation(*delta, max);
+                let offset = Point::new(0.0, completion.documentation_scroll());
+                if self
+                    .documentation
+                    .widget_mut()
+                    .inner_mut()
+                    .scroll_to(offset)
+                {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ResetFade,
+                        Target::Widget(self.scroll_id),
+                    ));
+                }
+                ctx.request_paint();
+                ctx.set_handled();
+                return;
+            }
+        }
+        // The mouse goes to what's under the popup while it's hidden
+        if !data.completion.shows_documentation()
+            && !event.should_propagate_to_hidden()
+        {
+            return;
+        }
+        self.documentation.event(ctx, event, data, env);
     }
 
-    pub fn current_items(&self) -> Vec<&ScoredCompletionItem> {
-        self.items.iter().filter(|i| i.score != 0).collect()
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        self.documentation.lifecycle(ctx, event, data, env);
     }
 
-    pub fn clear(&mut self) {
-        self.input = "".to_string();
-        self.items = Vec::new();
-        self.offset = 0;
-        self.index = 0;
-        self.scroll_offset = 0.0;
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if old_data.completion.index != data.completion.index
+            || old_data.completion.status != data.completion.status
+            || !old_data
+                .completion
+                .current_items()
+                .same(data.completion.current_items())
+        {
+            self.scroll_to_top(ctx, env);
+            ctx.request_layout();
+        }
+        if old_data.completion.documentation_expanded
+            != data.completion.documentation_expanded
+            || old_data.completion.shows_documentation()
+                != data.completion.shows_documentation()
+        {
+            ctx.request_layout();
+        }
+        self.documentation.update(ctx, data, env);
     }
 
-    pub fn cancel(&mut self, ctx: &mut EventCtx) {
-        self.clear();
-        self.request_paint(ctx);
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        _bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        if !data.completion.shows_documentation() {
+            return Size::ZERO;
+        }
+        let size = data.completion.documentation_box_size();
+        let bc = BoxConstraints::new(Size::ZERO, size);
+        self.content_size = self.documentation.layout(ctx, &bc, data, env);
+        self.documentation.set_origin(ctx, data, env, Point::ZERO);
+        ctx.set_paint_insets((10.0, 10.0, 10.0, 10.0));
+        size
     }
 
-    pub fn request_paint(&self, ctx: &mut EventCtx) {
-        ctx.submit_command(Command::new(
-            LAPCE_UI_COMMAND,
-            LapceUICommand::RequestPaint,
-            Target::Widget(self.widget_id),
-        ));
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        if data.completion.shows_documentation() {
+            let shadow_width = 5.0;
+            let rect = self.content_size.to_rect();
+            ctx.blurred_rect(
+                rect,
+                shadow_width,
+                data.config
+                    .get_color_unchecked(LapceTheme::LAPCE_DROPDOWN_SHADOW),
+            );
+            self.documentation.paint(ctx, data, env);
+
+            // There's more below what's scrolled into view
+            let scroll = self.documentation.widget().inner();
+            if scroll.child_size().height > scroll.offset().y + rect.height() + 1.0 {
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout("▼ more")
+                    .font(
+                        data.config.editor.font_family(),
+                        data.config.editor.font_size as f64 - 2.0,
+                    )
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                let size = text_layout.size();
+                let padding = 3.0;
+                let origin = Point::new(
+                    rect.x1 - size.width - padding - 10.0,
+                    rect.y1 - size.height - padding,
+                );
+                ctx.fill(
+                    Rect::from_origin_size(origin, size)
+                        .inflate(padding, padding / 2.0),
+                    data.config
+                        .get_color_unchecked(LapceTheme::COMPLETION_BACKGROUND),
+                );
+                ctx.draw_text(&text_layout, origin);
+            }
+        }
     }
+}
 
-    pub fn update(&mut self, input: String, completion_items: Vec<CompletionItem>) {
-        self.items = completion_items
-            .iter()
-            .enumerate()
-            .map(|(index, item)| ScoredCompletionItem {
-                item: item.to_owned(),
-                score: -1 - index as i64,
-                label_score: -1 - index as i64,
-                indices: Vec::new(),
-            })
-            .collect();
-        self.items
-            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Less));
-        self.input = input;
+struct CompletionDocumentationContent {
+    layout: druid::TextLayout<RichText>,
+}
+
+impl CompletionDocumentationContent {
+    const STARTING_Y: f64 = 5.0;
+    const STARTING_X: f64 = 10.0;
+
+    fn new() -> Self {
+        let mut layout = druid::TextLayout::new();
+        layout.set_text(RichText::new(ArcStr::from("")));
+        Self { layout }
     }
 }
 
-impl Default for CompletionState {
-    fn default() -> Self {
-        Self::new()
+impl Widget<LapceTabData> for CompletionDocumentationContent {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        _env: &Env,
+    ) {
+        if let Event::MouseMove(_) = event {
+            if data.completion.shows_documentation() {
+                ctx.set_handled();
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &LapceTabData,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        _env: &Env,
+    ) {
+        if old_data.completion.index != data.completion.index
+            || old_data.completion.status != data.completion.status
+            || old_data.config.editor.font_size != data.config.editor.font_size
+            || !old_data
+                .completion
+                .current_items()
+                .same(data.completion.current_items())
+        {
+            let text = data
+                .completion
+                .current_documentation()
+                .map(|documentation| {
+                    parse_documentation(documentation, &data.config)
+                })
+                .unwrap_or_else(|| RichText::new(ArcStr::from("")));
+            self.layout.set_text(text);
+            self.layout.set_font(
+                FontDescriptor::new(FontFamily::SYSTEM_UI)
+                    .with_size(data.config.editor.font_size as f64),
+            );
+            self.layout.set_text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                    .clone(),
+            );
+
+            if self.layout.needs_rebuild_after_update(ctx) {
+                ctx.request_layout();
+            }
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        let width = bc.max().width;
+        let max_width = width
+            - Self::STARTING_X
+            - env.get(theme::SCROLLBAR_WIDTH)
+            - env.get(theme::SCROLLBAR_PAD);
+
+        self.layout.set_wrap_width(max_width);
+        self.layout.rebuild_if_needed(ctx.text(), env);
+
+        let text_metrics = self.layout.layout_metrics();
+        ctx.set_baseline_offset(
+            text_metrics.size.height - text_metrics.first_baseline,
+        );
+
+        Size::new(width, text_metrics.size.height + Self::STARTING_Y * 2.0)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
+        let rect = ctx.region().bounding_box();
+        ctx.fill(
+            rect,
+            data.config
+                .get_color_unchecked(LapceTheme::COMPLETION_BACKGROUND),
+        );
+        self.layout
+            .draw(ctx, Point::new(Self::STARTING_X, Self::STARTING_Y));
     }
 }
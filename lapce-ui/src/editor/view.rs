@@ -167,7 +167,13 @@ impl LapceEditorView {
             LapceUICommand::EnsureRectVisible(rect) => {
                 self.ensure_rect_visible(ctx, data, *rect, env);
             }
-            LapceUICommand::ResolveCompletion(buffer_id, rev, offset, item) => {
+            LapceUICommand::ResolveCompletion(
+                buffer_id,
+                rev,
+                offset,
+                item,
+                then,
+            ) => {
                 if data.doc.id() != *buffer_id {
                     return;
                 }
@@ -180,6 +186,9 @@ impl LapceEditorView {
                 let offset = data.editor.new_cursor.offset();
                 let line = data.doc.buffer().line_of_offset(offset);
                 let _ = data.apply_completion_item(item);
+                if let Some(then) = then {
+                    data.receive_char(ctx, then);
+                }
                 let new_offset = data.editor.new_cursor.offset();
                 let new_line = data.doc.buffer().line_of_offset(new_offset);
                 if line != new_line {
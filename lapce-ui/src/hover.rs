@@ -3,7 +3,8 @@ use std::sync::Arc;
 use druid::{
     theme, ArcStr, BoxConstraints, Command, Data, Env, Event, EventCtx,
     FontDescriptor, FontFamily, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point,
-    RenderContext, Size, Target, TextLayout, UpdateCtx, Widget, WidgetId, WidgetPod,
+    Rect, RenderContext, Size, Target, TextLayout, UpdateCtx, Widget, WidgetId,
+    WidgetPod,
 };
 use lapce_data::{
     command::{LapceUICommand, LAPCE_UI_COMMAND},
@@ -23,6 +24,9 @@ pub struct HoverContainer {
         LapceIdentityWrapper<LapceScrollNew<LapceTabData, Hover>>,
     >,
     content_size: Size,
+    /// The rect of the completion popup while it shows, in window
+    /// coordinates, see `is_covered`
+    completion_rect: Option<Rect>,
 }
 impl HoverContainer {
     pub fn new(data: &HoverData) -> Self {
@@ -35,9 +39,19 @@ impl HoverContainer {
             scroll_id: data.scroll_id,
             hover: WidgetPod::new(hover),
             content_size: Size::ZERO,
+            completion_rect: None,
         }
     }
 
+    /// Whether the completion popup is over the hover at `window_origin`.
+    /// The hover steps aside then, since what's being typed matters more.
+    fn is_covered(&self, window_origin: Point) -> bool {
+        let rect = self.content_size.to_rect() + window_origin.to_vec2();
+        self.completion_rect
+            .map(|completion| completion.intersect(rect).area() > 0.0)
+            .unwrap_or(false)
+    }
+
     fn ensure_visible(
         &mut self,
         ctx: &mut UpdateCtx,
@@ -75,14 +89,30 @@ impl Widget<LapceTabData> for HoverContainer {
         match event {
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
-                if let LapceUICommand::UpdateHover(request_id, items) = command {
-                    let hover = Arc::make_mut(&mut data.hover);
-                    hover.receive(*request_id, items.clone());
-                    ctx.request_paint();
+                match command {
+                    LapceUICommand::UpdateHover(request_id, items) => {
+                        let hover = Arc::make_mut(&mut data.hover);
+                        hover.receive(*request_id, items.clone());
+                        ctx.request_paint();
+                    }
+                    LapceUICommand::CompletionOpened(rect) => {
+                        self.completion_rect = Some(*rect);
+                        ctx.request_paint();
+                    }
+                    LapceUICommand::CompletionClosed => {
+                        self.completion_rect = None;
+                        ctx.request_paint();
+                    }
+                    _ => {}
                 }
             }
             _ => {}
         }
+        if self.is_covered(ctx.window_origin())
+            && !event.should_propagate_to_hidden()
+        {
+            return;
+        }
         self.hover.event(ctx, event, data, env);
     }
 
@@ -166,7 +196,10 @@ impl Widget<LapceTabData> for HoverContainer {
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
-        if data.hover.status != HoverStatus::Inactive && !data.hover.is_empty() {
+        if data.hover.status != HoverStatus::Inactive
+            && !data.hover.is_empty()
+            && !self.is_covered(ctx.window_origin())
+        {
             let shadow_width = 5.0;
             let rect = self.content_size.to_rect();
             ctx.blurred_rect(
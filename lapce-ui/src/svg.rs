@@ -6,7 +6,7 @@ use lazy_static::lazy_static;
 use lsp_types::{CompletionItemKind, SymbolKind};
 use parking_lot::Mutex;
 
-use lapce_data::config::{Config, LOGO};
+use lapce_data::config::{Config, LapceTheme, LOGO};
 
 const ICONS_DIR: Dir = include_dir!("../icons");
 
@@ -98,36 +98,87 @@ pub fn symbol_svg_new(kind: &SymbolKind) -> Option<Svg> {
     get_svg(&format!("symbol-{}.svg", kind_str))
 }
 
+/// The icon of a completion item kind, with the color to tint it with
+pub struct CompletionIcon {
+    pub svg: Svg,
+    pub color: Color,
+}
+
 pub fn completion_svg(
     kind: Option<CompletionItemKind>,
     config: &Config,
-) -> Option<(Svg, Option<Color>)> {
-    let kind = kind?;
-    let kind_str = match kind {
-        CompletionItemKind::Method => "method",
-        CompletionItemKind::Function => "method",
-        CompletionItemKind::Enum => "enum",
-        CompletionItemKind::EnumMember => "enum-member",
-        CompletionItemKind::Class => "class",
-        CompletionItemKind::Variable => "variable",
-        CompletionItemKind::Struct => "structure",
-        CompletionItemKind::Keyword => "keyword",
-        CompletionItemKind::Constant => "constant",
-        CompletionItemKind::Property => "property",
-        CompletionItemKind::Field => "field",
-        CompletionItemKind::Interface => "interface",
-        CompletionItemKind::Snippet => "snippet",
-        CompletionItemKind::Module => "namespace",
-        _ => "string",
-    };
-    let theme_str = match kind_str {
-        "namespace" => "builtinType",
-        "variable" => "field",
-        _ => kind_str,
-    };
+) -> Option<CompletionIcon> {
+    let (kind_str, theme_str) = completion_kind_style(kind?);
+
+    Some(CompletionIcon {
+        svg: get_svg(&format!("symbol-{}.svg", kind_str))?,
+        color: config.get_color_unchecked(theme_str).clone(),
+    })
+}
 
-    Some((
-        get_svg(&format!("symbol-{}.svg", kind_str))?,
-        config.get_style_color(theme_str).cloned(),
-    ))
+/// The color of a completion item kind, the same as its icon has
+pub fn completion_kind_color(kind: CompletionItemKind, config: &Config) -> Color {
+    let (_, theme_str) = completion_kind_style(kind);
+    config.get_color_unchecked(theme_str).clone()
+}
+
+/// The icon name and the theme color of a completion item kind
+fn completion_kind_style(kind: CompletionItemKind) -> (&'static str, &'static str) {
+    match kind {
+        CompletionItemKind::Method => {
+            ("method", LapceTheme::COMPLETION_KIND_FUNCTION)
+        }
+        CompletionItemKind::Function => {
+            ("method", LapceTheme::COMPLETION_KIND_FUNCTION)
+        }
+        CompletionItemKind::Constructor => {
+            ("method", LapceTheme::COMPLETION_KIND_FUNCTION)
+        }
+        CompletionItemKind::Class => ("class", LapceTheme::COMPLETION_KIND_TYPE),
+        CompletionItemKind::Struct => {
+            ("structure", LapceTheme::COMPLETION_KIND_TYPE)
+        }
+        CompletionItemKind::Interface => {
+            ("interface", LapceTheme::COMPLETION_KIND_TYPE)
+        }
+        CompletionItemKind::Enum => ("enum", LapceTheme::COMPLETION_KIND_TYPE),
+        CompletionItemKind::TypeParameter => {
+            ("parameter", LapceTheme::COMPLETION_KIND_TYPE)
+        }
+        CompletionItemKind::Field => ("field", LapceTheme::COMPLETION_KIND_FIELD),
+        CompletionItemKind::Property => {
+            ("property", LapceTheme::COMPLETION_KIND_FIELD)
+        }
+        CompletionItemKind::Variable => {
+            ("variable", LapceTheme::COMPLETION_KIND_VARIABLE)
+        }
+        CompletionItemKind::Value => {
+            ("numeric", LapceTheme::COMPLETION_KIND_VARIABLE)
+        }
+        CompletionItemKind::Reference => {
+            ("variable", LapceTheme::COMPLETION_KIND_VARIABLE)
+        }
+        CompletionItemKind::Constant => {
+            ("constant", LapceTheme::COMPLETION_KIND_CONSTANT)
+        }
+        CompletionItemKind::EnumMember => {
+            ("enum-member", LapceTheme::COMPLETION_KIND_CONSTANT)
+        }
+        CompletionItemKind::Unit => ("ruler", LapceTheme::COMPLETION_KIND_CONSTANT),
+        CompletionItemKind::Color => ("color", LapceTheme::COMPLETION_KIND_CONSTANT),
+        CompletionItemKind::Keyword => {
+            ("keyword", LapceTheme::COMPLETION_KIND_KEYWORD)
+        }
+        CompletionItemKind::Operator => {
+            ("operator", LapceTheme::COMPLETION_KIND_KEYWORD)
+        }
+        CompletionItemKind::Module => {
+            ("namespace", LapceTheme::COMPLETION_KIND_MODULE)
+        }
+        CompletionItemKind::File => ("file", LapceTheme::COMPLETION_KIND_MODULE),
+        CompletionItemKind::Folder => ("file", LapceTheme::COMPLETION_KIND_MODULE),
+        CompletionItemKind::Snippet => ("snippet", LapceTheme::COMPLETION_KIND_TEXT),
+        CompletionItemKind::Text => ("string", LapceTheme::COMPLETION_KIND_TEXT),
+        CompletionItemKind::Event => ("event", LapceTheme::COMPLETION_KIND_TEXT),
+    }
 }
@@ -4,7 +4,7 @@ use druid::{
     piet::{PietTextLayout, Text, TextLayout, TextLayoutBuilder},
     BoxConstraints, Command, Data, Env, Event, EventCtx, FontFamily,
     InternalLifeCycle, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect,
-    RenderContext, Size, Target, Widget, WidgetExt, WidgetId, WidgetPod,
+    RenderContext, Size, Target, Vec2, Widget, WidgetExt, WidgetId, WidgetPod,
     WindowConfig,
 };
 use itertools::Itertools;
@@ -36,12 +36,22 @@ use serde::Deserialize;
 use xi_rope::Rope;
 
 use crate::{
-    activity::ActivityBar, alert::AlertBox, code_action::CodeAction,
-    completion::CompletionContainer, explorer::FileExplorer, hover::HoverContainer,
-    palette::NewPalette, picker::FilePicker, plugin::Plugin,
-    problem::new_problem_panel, search::new_search_panel,
-    settings::LapceSettingsPanel, source_control::new_source_control_panel,
-    split::split_data_widget, status::LapceStatusNew, svg::get_svg,
+    activity::ActivityBar,
+    alert::AlertBox,
+    code_action::CodeAction,
+    completion::{CompletionContainer, CompletionDocumentation},
+    explorer::FileExplorer,
+    hover::HoverContainer,
+    palette::NewPalette,
+    picker::FilePicker,
+    plugin::Plugin,
+    problem::new_problem_panel,
+    search::new_search_panel,
+    settings::LapceSettingsPanel,
+    source_control::new_source_control_panel,
+    split::split_data_widget,
+    status::LapceStatusNew,
+    svg::get_svg,
     terminal::TerminalPanel,
 };
 
@@ -62,6 +72,7 @@ pub struct LapceTabNew {
     activity: WidgetPod<LapceTabData, ActivityBar>,
     main_split: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     completion: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+    completion_documentation: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     hover: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     palette: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     code_action: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
@@ -89,6 +100,8 @@ impl LapceTabNew {
 
         let activity = ActivityBar::new();
         let completion = CompletionContainer::new(&data.completion);
+        let completion_documentation =
+            CompletionDocumentation::new(&data.completion);
         let hover = HoverContainer::new(&data.hover);
         let palette = NewPalette::new(data);
         let status = LapceStatusNew::new();
@@ -130,6 +143,9 @@ impl LapceTabNew {
             activity: WidgetPod::new(activity),
             main_split: WidgetPod::new(main_split.boxed()),
             completion: WidgetPod::new(completion.boxed()),
+            completion_documentation: WidgetPod::new(
+                completion_documentation.boxed(),
+            ),
             hover: WidgetPod::new(hover.boxed()),
             code_action: WidgetPod::new(code_action.boxed()),
             picker: WidgetPod::new(picker.boxed()),
@@ -902,6 +918,16 @@ impl LapceTabNew {
                         }
                         ctx.set_handled();
                     }
+                    LapceUICommand::UpdateCompletionTriggerCharacters(
+                        buffer_id,
+                        characters,
+                    ) => {
+                        let completion = Arc::make_mut(&mut data.completion);
+                        completion
+                            .trigger_characters
+                            .insert(*buffer_id, characters.clone());
+                        ctx.set_handled();
+                    }
                     LapceUICommand::UpdateSemanticStyles(_id, path, rev, styles) => {
                         let doc = data.main_split.open_docs.get_mut(path).unwrap();
                         if doc.rev() == *rev {
@@ -1068,6 +1094,7 @@ impl Widget<LapceTabData> for LapceTabNew {
             || event.should_propagate_to_hidden()
         {
             self.completion.event(ctx, event, data, env);
+            self.completion_documentation.event(ctx, event, data, env);
         }
         if data.hover.status == HoverStatus::Done
             || event.should_propagate_to_hidden()
@@ -1134,6 +1161,8 @@ impl Widget<LapceTabData> for LapceTabNew {
         self.code_action.lifecycle(ctx, event, data, env);
         self.status.lifecycle(ctx, event, data, env);
         self.completion.lifecycle(ctx, event, data, env);
+        self.completion_documentation
+            .lifecycle(ctx, event, data, env);
         self.hover.lifecycle(ctx, event, data, env);
         self.picker.lifecycle(ctx, event, data, env);
         self.settings.lifecycle(ctx, event, data, env);
@@ -1187,10 +1216,30 @@ impl Widget<LapceTabData> for LapceTabNew {
             ctx.request_layout();
         }
 
+        // Tell the rest of the window when the completion popup shows up or
+        // goes away, so that other popups, like the hover, can step aside
+        if old_data.completion.is_visible() != data.completion.is_visible() {
+            let command = if data.completion.is_visible() {
+                let (rect, _) =
+                    data.completion_rect(ctx.text(), ctx.size(), &data.config);
+                LapceUICommand::CompletionOpened(
+                    rect + ctx.window_origin().to_vec2(),
+                )
+            } else {
+                LapceUICommand::CompletionClosed
+            };
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                command,
+                Target::Auto,
+            ));
+        }
+
         self.palette.update(ctx, data, env);
         self.activity.update(ctx, data, env);
         self.main_split.update(ctx, data, env);
         self.completion.update(ctx, data, env);
+        self.completion_documentation.update(ctx, data, env);
         self.hover.update(ctx, data, env);
         self.code_action.update(ctx, data, env);
         self.status.update(ctx, data, env);
@@ -1451,11 +1500,46 @@ impl Widget<LapceTabData> for LapceTabNew {
         self.main_split_height = main_split_size.height;
 
         if data.completion.status != CompletionStatus::Inactive {
-            let completion_origin =
-                data.completion_origin(ctx.text(), self_size, &data.config);
-            self.completion.layout(ctx, bc, data, env);
+            let (completion_rect, completion_above) =
+                data.completion_rect(ctx.text(), self_size, &data.config);
+            let completion_origin = completion_rect.origin();
+            let completion_bc =
+                BoxConstraints::new(Size::ZERO, completion_rect.size());
+            let completion_size =
+                self.completion.layout(ctx, &completion_bc, data, env);
             self.completion
                 .set_origin(ctx, data, env, completion_origin);
+
+            let documentation_size =
+                self.completion_documentation.layout(ctx, bc, data, env);
+            let mut documentation_origin =
+                completion_origin + Vec2::new(completion_size.width, 0.0);
+            if completion_above
+                || documentation_origin.y + documentation_size.height
+                    > self_size.height
+            {
+                // Line the bottoms up when the list is above the cursor,
+                // so that the documentation doesn't cover the cursor line
+                documentation_origin.y = (completion_origin.y
+                    + completion_size.height
+                    - documentation_size.height)
+                    .max(0.0);
+            }
+            if documentation_origin.x + documentation_size.width + 1.0
+                > self_size.width
+            {
+                documentation_origin.x =
+                    completion_origin.x - documentation_size.width;
+            }
+            if documentation_origin.x <= 0.0 {
+                documentation_origin.x = 0.0;
+            }
+            self.completion_documentation.set_origin(
+                ctx,
+                data,
+                env,
+                documentation_origin,
+            );
         }
 
         if data.hover.status == HoverStatus::Done {
@@ -1573,6 +1657,7 @@ impl Widget<LapceTabData> for LapceTabNew {
         // }
         self.status.paint(ctx, data, env);
         self.completion.paint(ctx, data, env);
+        self.completion_documentation.paint(ctx, data, env);
         self.hover.paint(ctx, data, env);
         self.code_action.paint(ctx, data, env);
         self.palette.paint(ctx, data, env);
@@ -223,6 +223,8 @@ impl Widget<LapceWindowData> for LapceWindowNew {
                                 Config::load(&tab.workspace.clone())
                                     .unwrap_or_default(),
                             );
+                            Arc::make_mut(&mut tab.completion)
+                                .update_config(&tab.config);
                         }
                         Arc::make_mut(&mut data.keypress)
                             .update_keymaps(&data.config);